@@ -0,0 +1,50 @@
+//! How long turning one chapter's HTML into records takes, from rendering
+//! through the line cleanups to the context window.
+//!
+//! Run with `cargo bench`; Criterion compares each run against the last one.
+
+use std::path::Path;
+
+use cosmere_epub_parser::{Chapter, ChapterParser, Granularity, IndexableBook, ParseOptions};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// A made-up chapter shaped like a real one: a heading, an epigraph, sixty
+/// paragraphs with italics and dialogue, scene breaks, small caps, and a
+/// footnote.
+const CHAPTER: &[u8] = include_bytes!("fixtures/chapter.xhtml");
+
+fn book() -> IndexableBook {
+    IndexableBook {
+        title: String::from("Benchmark"),
+        has_epigraphs: true,
+        ..IndexableBook::default()
+    }
+}
+
+fn parse_chapter(c: &mut Criterion) {
+    let book = book();
+    let chapter = Chapter {
+        html: CHAPTER,
+        title: "Chapter 7",
+        spine_id: "chapter07",
+        spine_index: 7,
+        file_name: "chapter07.xhtml",
+    };
+    for (name, granularity) in [
+        ("paragraphs", Granularity::Paragraph),
+        ("sentences", Granularity::Sentence),
+    ] {
+        let options = ParseOptions {
+            granularity,
+            ..ParseOptions::default()
+        };
+        let parser = ChapterParser::new(&book, Path::new("benchmark.epub"), &options)
+            .expect("default options are valid");
+        c.bench_function(&format!("parse_chapter/{name}"), |b| {
+            b.iter(|| parser.parse_chapter(black_box(&chapter)).unwrap());
+        });
+    }
+}
+
+criterion_group!(benches, parse_chapter);
+criterion_main!(benches);
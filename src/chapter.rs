@@ -0,0 +1,675 @@
+use std::{
+    io::{Read, Seek},
+    path::Path,
+    sync::OnceLock,
+};
+
+use epub::doc::{EpubDoc, NavPoint};
+use html2text::{from_read_with_decorator, render::text_renderer::TrivialDecorator};
+use regex::{Captures, Regex};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{html::attribute, DEFAULT_LANGUAGE};
+
+/// Spine ids or file names that show up before the first real chapter.
+const FRONT_MATTER_PATTERNS: [&str; 7] = [
+    "cover",
+    "title",
+    "copyright",
+    "toc",
+    "contents",
+    "dedication",
+    "frontmatter",
+];
+
+/// Spine ids or file names that show up after the last real chapter.
+const BACK_MATTER_PATTERNS: [&str; 6] = [
+    "acknowledg",
+    "about",
+    "alsoby",
+    "also-by",
+    "copyright",
+    "backmatter",
+];
+
+/// Where a spine entry sits in the book, going by its EPUB 3 `epub:type`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SectionKind {
+    Front,
+    Body,
+    Back,
+}
+
+/// `epub:type` values, and the part of the book each one belongs to.
+const SECTION_TYPES: [(&str, SectionKind); 29] = [
+    ("bodymatter", SectionKind::Body),
+    ("chapter", SectionKind::Body),
+    ("prologue", SectionKind::Body),
+    ("epilogue", SectionKind::Body),
+    ("part", SectionKind::Body),
+    ("interlude", SectionKind::Body),
+    ("frontmatter", SectionKind::Front),
+    ("cover", SectionKind::Front),
+    ("titlepage", SectionKind::Front),
+    ("halftitlepage", SectionKind::Front),
+    ("copyright-page", SectionKind::Front),
+    ("toc", SectionKind::Front),
+    ("landmarks", SectionKind::Front),
+    ("dedication", SectionKind::Front),
+    ("foreword", SectionKind::Front),
+    ("preface", SectionKind::Front),
+    ("seriespage", SectionKind::Front),
+    ("imprint", SectionKind::Front),
+    ("backmatter", SectionKind::Back),
+    ("acknowledgments", SectionKind::Back),
+    ("afterword", SectionKind::Back),
+    ("appendix", SectionKind::Back),
+    ("glossary", SectionKind::Back),
+    ("index", SectionKind::Back),
+    ("colophon", SectionKind::Back),
+    ("bibliography", SectionKind::Back),
+    ("contributors", SectionKind::Back),
+    ("other-credits", SectionKind::Back),
+    ("endnotes", SectionKind::Back),
+];
+
+/// Guesses the first and last spine indices that hold actual chapters by
+/// skipping over front and back matter at either end of the spine.
+///
+/// Entries tagged with an EPUB 3 `epub:type` are classified by that tag;
+/// anything untagged falls back to guessing from its id and file name.
+pub fn detect_chapter_range<R: Read + Seek>(doc: &mut EpubDoc<R>) -> (usize, usize) {
+    let spine = doc.spine.clone();
+    let entries: Vec<(String, Option<SectionKind>)> = spine
+        .iter()
+        .map(|id| {
+            let kind = doc
+                .get_resource_str(id)
+                .and_then(|(xhtml, _mime)| section_kind(&xhtml));
+            (spine_entry_name(doc, id), kind)
+        })
+        .collect();
+    let matches_any =
+        |name: &str, patterns: &[&str]| patterns.iter().any(|pattern| name.contains(pattern));
+    // Matter from the other end of the book still isn't a chapter
+    let is_matter = |(name, kind): &(String, Option<SectionKind>), patterns: &[&str]| match kind {
+        Some(kind) => *kind != SectionKind::Body,
+        None => matches_any(name, patterns),
+    };
+
+    let first = entries
+        .iter()
+        .position(|it| !is_matter(it, &FRONT_MATTER_PATTERNS))
+        .unwrap_or(0);
+    let last = entries
+        .iter()
+        .rposition(|it| !is_matter(it, &BACK_MATTER_PATTERNS))
+        .unwrap_or_else(|| entries.len().saturating_sub(1))
+        .max(first);
+    (first, last)
+}
+
+/// The kind of section a spine entry's XHTML declares on its `<body>` or its
+/// first `<section>`, if it uses `epub:type` at all.
+fn section_kind(xhtml: &str) -> Option<SectionKind> {
+    static SECTION_TAG: OnceLock<Regex> = OnceLock::new();
+    let section_tag =
+        SECTION_TAG.get_or_init(|| Regex::new(r"<(?:body|section)\b[^>]*>").expect("valid regex"));
+    let types = section_tag
+        .find_iter(xhtml)
+        .find_map(|tag| attribute(tag.as_str(), "epub:type"))?;
+    types.split_whitespace().find_map(|it| {
+        SECTION_TYPES
+            .iter()
+            .find(|(name, _)| it.eq_ignore_ascii_case(name))
+            .map(|(_, kind)| *kind)
+    })
+}
+
+/// The spine id plus the file it points at, lowercased for matching.
+fn spine_entry_name<R: Read + Seek>(doc: &EpubDoc<R>, id: &str) -> String {
+    let file_name = doc
+        .resources
+        .get(id)
+        .and_then(|(path, _mime)| Path::new(path).file_name())
+        .map(|it| it.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    format!("{id} {file_name}").to_lowercase()
+}
+
+/// The title readers see for the chapter at `chapter_index`, whose XHTML is
+/// `html`: its label in the ePub's table of contents, or else its heading, or
+/// else a guess from its spine id. The guesses are English, so other
+/// editions get the bare spine id instead.
+pub fn chapter_title<R: Read + Seek>(
+    book_title: &str,
+    doc: &EpubDoc<R>,
+    chapter_index: usize,
+    html: &[u8],
+) -> String {
+    if is_untitled(book_title) {
+        return String::new();
+    }
+    let language = doc.mdata("language");
+    let english = is_english(language.as_deref().unwrap_or(DEFAULT_LANGUAGE));
+    toc_label(doc, chapter_index)
+        .or_else(|| {
+            let heading = heading_text(&String::from_utf8_lossy(html))?;
+            if heading.eq_ignore_ascii_case(book_title) {
+                // A running head, not the chapter's own title
+                return None;
+            }
+            match heading.parse::<u32>() {
+                Ok(number) if english => Some(format!("Chapter {number}")),
+                _ => Some(heading),
+            }
+        })
+        .unwrap_or_else(|| {
+            let spine_id = &doc.spine[chapter_index];
+            if english {
+                pretty_chapter(book_title, spine_id)
+            } else {
+                spine_id.clone()
+            }
+        })
+}
+
+/// The text of the first `<h1>` or `<h2>` in some XHTML, or failing that
+/// its first heading of any level, the same line the decorator would render
+/// with a `#`. An image in one is read as its `alt` text, like a chapter
+/// number set as a picture, and headings with no text are passed over.
+fn heading_text(xhtml: &str) -> Option<String> {
+    static HEADING: OnceLock<Regex> = OnceLock::new();
+    static IMG: OnceLock<Regex> = OnceLock::new();
+    let heading = HEADING.get_or_init(|| {
+        Regex::new(r"(?is)<h([1-6])\b[^>]*>(.*?)</h[1-6]\s*>").expect("valid regex")
+    });
+    let img = IMG.get_or_init(|| Regex::new(r"(?i)<img\b[^>]*>").expect("valid regex"));
+    let mut headings = heading.captures_iter(xhtml).filter_map(|caps| {
+        let inner = img.replace_all(&caps[2], |caps: &Captures| {
+            attribute(&caps[0], "alt").unwrap_or_default().to_owned()
+        });
+        let text = from_read_with_decorator(inner.as_bytes(), usize::MAX, TrivialDecorator::new());
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        (!text.is_empty()).then(|| (caps[1].to_string(), text))
+    });
+    let first = headings.next()?;
+    if first.0 == "1" || first.0 == "2" {
+        return Some(first.1);
+    }
+    Some(
+        headings
+            .find(|(level, _)| level == "1" || level == "2")
+            .unwrap_or(first)
+            .1,
+    )
+}
+
+/// Whether a `dc:language` code like `en-GB` is English.
+fn is_english(language: &str) -> bool {
+    language
+        .split(['-', '_'])
+        .next()
+        .is_some_and(|it| it.eq_ignore_ascii_case("en") || it.eq_ignore_ascii_case("eng"))
+}
+
+/// The table of contents label pointing at the spine entry `chapter_index`.
+///
+/// Entries linking to a spot inside the file still count, and nested entries
+/// are searched too; the first match in reading order wins.
+pub fn toc_label<R: Read + Seek>(doc: &EpubDoc<R>, chapter_index: usize) -> Option<String> {
+    let (path, _mime) = doc.resources.get(doc.spine.get(chapter_index)?)?;
+    let mut pending: Vec<&NavPoint> = doc.toc.iter().rev().collect();
+    while let Some(nav_point) = pending.pop() {
+        let content = nav_point.content.to_string_lossy();
+        let file = content.split('#').next().unwrap_or_default();
+        let label = nav_point.label.trim();
+        if Path::new(file) == path && !label.is_empty() {
+            return Some(label.to_string());
+        }
+        pending.extend(nav_point.children.iter().rev());
+    }
+    None
+}
+
+/// The spine range under the table of contents entry labelled `label`, for a
+/// work in an omnibus: from the spine entry it points at up to just before
+/// the next entry that isn't nested under it, or the end of the chapters
+/// [`detect_chapter_range`] finds. Labels that start with `label`, like
+/// `Allomancer Jak and the Pits of Eltania, Episodes Twenty-Eight through
+/// Thirty`, count too, ignoring case.
+pub fn toc_range<R: Read + Seek>(doc: &mut EpubDoc<R>, label: &str) -> Option<(usize, usize)> {
+    let label = label.to_lowercase();
+    let (first, next) = {
+        // Every entry in reading order, with how many are nested under it
+        let mut entries = Vec::new();
+        flatten_toc(&doc.toc, &mut entries);
+        let at = entries
+            .iter()
+            .position(|(it, _)| it.label.trim().to_lowercase().starts_with(&label))?;
+        let first = spine_index(doc, entries[at].0)?;
+        let next = entries[at + entries[at].1 + 1..]
+            .iter()
+            .filter_map(|(it, _)| spine_index(doc, it))
+            .find(|index| *index > first);
+        (first, next)
+    };
+    let last = match next {
+        Some(next) => next - 1,
+        None => detect_chapter_range(doc).1.max(first),
+    };
+    Some((first, last))
+}
+
+/// Adds `nav_points` and everything nested under them to `entries` in
+/// reading order, each with how many entries are nested under it.
+fn flatten_toc<'a>(nav_points: &'a [NavPoint], entries: &mut Vec<(&'a NavPoint, usize)>) {
+    for nav_point in nav_points {
+        let at = entries.len();
+        entries.push((nav_point, 0));
+        flatten_toc(&nav_point.children, entries);
+        entries[at].1 = entries.len() - at - 1;
+    }
+}
+
+/// The spine index of the file `nav_point` links to.
+fn spine_index<R: Read + Seek>(doc: &EpubDoc<R>, nav_point: &NavPoint) -> Option<usize> {
+    let content = nav_point.content.to_string_lossy();
+    let file = content.split('#').next().unwrap_or_default();
+    doc.spine.iter().position(|id| {
+        doc.resources
+            .get(id)
+            .is_some_and(|(path, _mime)| path == Path::new(file))
+    })
+}
+
+/// Books whose chapters go untitled in the output.
+fn is_untitled(book_title: &str) -> bool {
+    book_title.eq_ignore_ascii_case("The Hope of Elantris")
+}
+
+/// Turns a spine id like `chapter07` or `Day_42.html` into the title readers see.
+#[allow(clippy::case_sensitive_file_extension_comparisons)]
+pub fn pretty_chapter(book_title: &str, raw_chapter: &str) -> String {
+    let lowercase_chapter = raw_chapter.to_ascii_lowercase();
+    if is_untitled(book_title) {
+        String::new()
+    } else if lowercase_chapter == "pre" {
+        String::from("Prelude")
+    } else if lowercase_chapter == "prologue" || lowercase_chapter == "pro" {
+        String::from("Prologue")
+    } else if lowercase_chapter == "epilogue" || lowercase_chapter == "epi" {
+        String::from("Epilogue")
+    } else if let Some((part, chapter)) = part_and_chapter_numbers(&lowercase_chapter) {
+        format!("Part {part}, Chapter {chapter}")
+    } else if let Some(rest) = lowercase_chapter.strip_prefix("interlude") {
+        parse_number(rest).map_or_else(|| String::from("Interlude"), |it| format!("Interlude {it}"))
+    } else if let Some(number) = lowercase_chapter
+        .strip_prefix("part")
+        .and_then(parse_number)
+    {
+        format!("Part {number}")
+    } else if let Some(number) = chapter_number(&lowercase_chapter) {
+        format!("Chapter {number}")
+    } else if let Some((part, chapter)) = secret_history_numbers(raw_chapter) {
+        format!("Part {part}, Chapter {chapter}")
+    } else if let Some(title) = map_by_hand(raw_chapter) {
+        String::from(title)
+    } else if let Some(number) = embedded_chapter_number(&lowercase_chapter) {
+        format!("Chapter {number}")
+    } else {
+        raw_chapter.to_string()
+    }
+}
+
+/// The part and chapter numbers in a spine id like `part01_chapter03.xhtml`
+/// or `p2-ch14`.
+fn part_and_chapter_numbers(lowercase_chapter: &str) -> Option<(u32, u32)> {
+    static ID: OnceLock<Regex> = OnceLock::new();
+    let id = ID.get_or_init(|| {
+        Regex::new(r"^(?:part|pt|p)[_-]?(\d+)[_.-]*(?:chapter|ch|c)[_-]?(\d+)(?:\.x?html?)?$")
+            .expect("valid regex")
+    });
+    let caps = id.captures(lowercase_chapter)?;
+    Some((caps[1].parse().ok()?, caps[2].parse().ok()?))
+}
+
+/// The number in a spine id with a chapter number somewhere inside it, like
+/// `book_ch05` or `text-c12.xhtml`.
+fn embedded_chapter_number(lowercase_chapter: &str) -> Option<u32> {
+    static ID: OnceLock<Regex> = OnceLock::new();
+    let id = ID.get_or_init(|| {
+        Regex::new(r"(?:^|[^a-z])(?:chapter|ch|c)[_-]?(\d+)(?:[^\d]|$)").expect("valid regex")
+    });
+    id.captures(lowercase_chapter)?[1].parse().ok()
+}
+
+/// The number in a spine id like `chapter07`, `c12`, or `chapter_twenty-three`.
+fn chapter_number(lowercase_chapter: &str) -> Option<u32> {
+    let rest = lowercase_chapter
+        .strip_prefix("chapter")
+        .or_else(|| lowercase_chapter.strip_prefix('c'))?;
+    parse_number(rest)
+}
+
+/// The part and chapter numbers in a chapter title like `Chapter 12`,
+/// `Part 2, Chapter 3`, or `Chapter Twelve: The Storm`, for sorting chapters
+/// numerically. Prologues, interludes, and the like have neither.
+pub fn chapter_numbers(title: &str) -> (Option<u32>, Option<u32>) {
+    let lowercase_title = title.to_lowercase();
+    let mut part = None;
+    let mut chapter = None;
+    for piece in lowercase_title.split([',', ':']).map(str::trim) {
+        if let Some(rest) = piece.strip_prefix("part ") {
+            part = part.or_else(|| parse_number(rest));
+        } else if let Some(rest) = piece.strip_prefix("chapter") {
+            chapter = chapter.or_else(|| parse_number(rest));
+        } else if !piece.is_empty() && piece.chars().all(|it| it.is_ascii_digit()) {
+            // TOCs that label chapters with just the number
+            chapter = chapter.or_else(|| piece.parse().ok());
+        }
+    }
+    (part, chapter)
+}
+
+/// What kind of section of the book a chapter is, for laying out its outline.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum SectionType {
+    Chapter,
+    Prologue,
+    Epilogue,
+    Interlude,
+    /// A divider opening a numbered part of the book.
+    Part,
+    #[default]
+    Other,
+}
+
+/// The kind of section a chapter is, going by its title.
+pub fn section_type(title: &str) -> SectionType {
+    let lowercase_title = title.trim().to_lowercase();
+    if lowercase_title.starts_with("prologue") || lowercase_title.starts_with("prelude") {
+        SectionType::Prologue
+    } else if lowercase_title.starts_with("epilogue") {
+        SectionType::Epilogue
+    } else if lowercase_title.starts_with("interlude") {
+        SectionType::Interlude
+    } else {
+        match chapter_numbers(title) {
+            (_, Some(_)) => SectionType::Chapter,
+            (Some(_), None) => SectionType::Part,
+            (None, None) => SectionType::Other,
+        }
+    }
+}
+
+/// A number written in digits or words, ignoring anything else around it.
+fn parse_number(text: &str) -> Option<u32> {
+    let digits: String = text.chars().filter(char::is_ascii_digit).collect();
+    if digits.is_empty() {
+        parse_number_words(text)
+    } else {
+        digits.parse().ok()
+    }
+}
+
+/// English number words, longest first so greedy matching works on ids that
+/// run the words together like `twentythree`.
+const NUMBER_WORDS: [(&str, u32); 28] = [
+    ("seventeen", 17),
+    ("thirteen", 13),
+    ("fourteen", 14),
+    ("eighteen", 18),
+    ("nineteen", 19),
+    ("fifteen", 15),
+    ("sixteen", 16),
+    ("seventy", 70),
+    ("hundred", 100),
+    ("eleven", 11),
+    ("twelve", 12),
+    ("twenty", 20),
+    ("thirty", 30),
+    ("eighty", 80),
+    ("ninety", 90),
+    ("forty", 40),
+    ("fifty", 50),
+    ("sixty", 60),
+    ("three", 3),
+    ("seven", 7),
+    ("eight", 8),
+    ("four", 4),
+    ("five", 5),
+    ("nine", 9),
+    ("one", 1),
+    ("two", 2),
+    ("six", 6),
+    ("ten", 10),
+];
+
+/// Parses spelled-out numbers like "twenty-three" or "one hundred and one",
+/// ignoring any separators between the words.
+fn parse_number_words(text: &str) -> Option<u32> {
+    let letters: String = text.chars().filter(char::is_ascii_alphabetic).collect();
+    let mut rest = letters.as_str();
+    let mut total = 0;
+    let mut found_any = false;
+    while !rest.is_empty() {
+        if let Some(after_and) = rest.strip_prefix("and") {
+            rest = after_and;
+            continue;
+        }
+        let (word, value) = NUMBER_WORDS
+            .iter()
+            .find(|(word, _)| rest.starts_with(word))?;
+        if *value == 100 {
+            total = total.max(1) * 100;
+        } else {
+            total += value;
+        }
+        found_any = true;
+        rest = &rest[word.len()..];
+    }
+    found_any.then_some(total)
+}
+
+/// The part and chapter numbers in a Secret History file name like
+/// `x1_2.html` or `xII_14.html`, with the part in digits or Roman numerals.
+fn secret_history_numbers(raw_chapter: &str) -> Option<(u32, u32)> {
+    static FILE_NAME: OnceLock<Regex> = OnceLock::new();
+    let file_name = FILE_NAME
+        .get_or_init(|| Regex::new(r"(?i)^x(\d+|[ivxl]+)_(\d+)\.x?html$").expect("valid regex"));
+    let caps = file_name.captures(raw_chapter)?;
+    let part = caps[1].parse().ok().or_else(|| parse_roman(&caps[1]))?;
+    let chapter = caps[2].parse().ok()?;
+    Some((part, chapter))
+}
+
+/// The value of a Roman numeral like `XIV`, ignoring case.
+fn parse_roman(numeral: &str) -> Option<u32> {
+    let values = numeral
+        .chars()
+        .map(|it| match it.to_ascii_lowercase() {
+            'i' => Some(1),
+            'v' => Some(5),
+            'x' => Some(10),
+            'l' => Some(50),
+            _ => None,
+        })
+        .collect::<Option<Vec<i64>>>()?;
+    let mut total = 0;
+    for (i, value) in values.iter().enumerate() {
+        // A smaller numeral before a bigger one is subtracted, as in `IV`
+        if values.get(i + 1).is_some_and(|next| next > value) {
+            total -= value;
+        } else {
+            total += value;
+        }
+    }
+    u32::try_from(total).ok().filter(|it| *it > 0)
+}
+
+/// Titles for spine ids the rules above get wrong.
+fn map_by_hand(raw_chapter: &str) -> Option<&'static str> {
+    let title = match raw_chapter {
+        "Prologue.html" => "Prologue",
+        "Day_02.html" => "Day Two",
+        "Day_03.html" => "Day Three",
+        "Day_05.html" => "Day Five",
+        "Day_12.html" => "Day Twelve",
+        "Day_17.html" => "Day Seventeen",
+        "Day_30.html" => "Day Thirty",
+        "Day_42.html" => "Day Forty-Two",
+        "Day_58.html" => "Day Fifty-Eight",
+        "Day_59.html" => "Day Fifty-Nine",
+        "Day_70.html" => "Day Seventy",
+        "Day_76.html" => "Day Seventy-Six",
+        "Day_85.html" => "Day Eighty-Five",
+        "Day_97.html" => "Day Ninety-Seven",
+        "Day_98.html" => "Day Ninety-Eight",
+        "Epilogue.html" => "Epilogue: Day One Hundred and One",
+        "p01a_c01" => "Interlude 1",
+        "p01a_c02" => "Interlude 2",
+        "p01a_c03" => "Interlude 3",
+        "p02a_c04" => "Interlude 4",
+        "p02a_c05" => "Interlude 5",
+        "p02a_c06" => "Interlude 6",
+        "p03a_c07" => "Interlude 7",
+        "p03a_c08" => "Interlude 8",
+        "p03a_c09" => "Interlude 9",
+        "end-note" => "Endnote",
+        _ => return None,
+    };
+    Some(title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{map_by_hand, parse_number_words, parse_roman, pretty_chapter};
+
+    const BOOK: &str = "The Way of Kings";
+
+    #[test]
+    fn numbered_chapters_with_leading_zeros() {
+        assert_eq!(pretty_chapter(BOOK, "chapter07"), "Chapter 7");
+        assert_eq!(pretty_chapter(BOOK, "Chapter001"), "Chapter 1");
+        assert_eq!(pretty_chapter(BOOK, "c012"), "Chapter 12");
+        assert_eq!(pretty_chapter(BOOK, "chapter_twenty-three"), "Chapter 23");
+        assert_eq!(
+            pretty_chapter(BOOK, "part01_chapter03"),
+            "Part 1, Chapter 3"
+        );
+        assert_eq!(pretty_chapter(BOOK, "p2-ch14.xhtml"), "Part 2, Chapter 14");
+        assert_eq!(pretty_chapter(BOOK, "part02"), "Part 2");
+        assert_eq!(pretty_chapter(BOOK, "book_ch05"), "Chapter 5");
+    }
+
+    #[test]
+    fn prologues_and_epilogues_ignore_case() {
+        for id in ["prologue", "Prologue", "PROLOGUE", "pro", "Pro"] {
+            assert_eq!(pretty_chapter(BOOK, id), "Prologue", "{id}");
+        }
+        for id in ["epilogue", "Epilogue", "EPILOGUE", "epi", "EPI"] {
+            assert_eq!(pretty_chapter(BOOK, id), "Epilogue", "{id}");
+        }
+        assert_eq!(pretty_chapter(BOOK, "PRE"), "Prelude");
+        assert_eq!(pretty_chapter(BOOK, "Interlude"), "Interlude");
+        assert_eq!(pretty_chapter(BOOK, "interlude_03"), "Interlude 3");
+    }
+
+    #[test]
+    fn the_hope_of_elantris_goes_untitled() {
+        assert_eq!(pretty_chapter("The Hope of Elantris", "chapter01"), "");
+        assert_eq!(pretty_chapter("the hope of elantris", "prologue"), "");
+    }
+
+    #[test]
+    fn secret_history_file_names() {
+        assert_eq!(pretty_chapter(BOOK, "x1_2.html"), "Part 1, Chapter 2");
+        assert_eq!(pretty_chapter(BOOK, "xII_14.html"), "Part 2, Chapter 14");
+        assert_eq!(pretty_chapter(BOOK, "xiv_3.xhtml"), "Part 4, Chapter 3");
+        assert_eq!(pretty_chapter(BOOK, "X6_1.HTML"), "Part 6, Chapter 1");
+    }
+
+    #[test]
+    fn secret_history_file_names_out_of_range() {
+        // Too short, missing a number, or not a numeral at all: left as they are
+        for id in [
+            "x.html",
+            "x_.html",
+            "x1_.html",
+            "x_2.html",
+            "xq_2.html",
+            "x1_2",
+        ] {
+            assert_eq!(pretty_chapter(BOOK, id), id);
+        }
+        // A chapter number too big for a u32
+        assert_eq!(
+            pretty_chapter(BOOK, "x1_99999999999.html"),
+            "x1_99999999999.html"
+        );
+    }
+
+    #[test]
+    fn hand_mapped_ids() {
+        let expected = [
+            ("Prologue.html", "Prologue"),
+            ("Day_02.html", "Day Two"),
+            ("Day_03.html", "Day Three"),
+            ("Day_05.html", "Day Five"),
+            ("Day_12.html", "Day Twelve"),
+            ("Day_17.html", "Day Seventeen"),
+            ("Day_30.html", "Day Thirty"),
+            ("Day_42.html", "Day Forty-Two"),
+            ("Day_58.html", "Day Fifty-Eight"),
+            ("Day_59.html", "Day Fifty-Nine"),
+            ("Day_70.html", "Day Seventy"),
+            ("Day_76.html", "Day Seventy-Six"),
+            ("Day_85.html", "Day Eighty-Five"),
+            ("Day_97.html", "Day Ninety-Seven"),
+            ("Day_98.html", "Day Ninety-Eight"),
+            ("Epilogue.html", "Epilogue: Day One Hundred and One"),
+            ("p01a_c01", "Interlude 1"),
+            ("p01a_c02", "Interlude 2"),
+            ("p01a_c03", "Interlude 3"),
+            ("p02a_c04", "Interlude 4"),
+            ("p02a_c05", "Interlude 5"),
+            ("p02a_c06", "Interlude 6"),
+            ("p03a_c07", "Interlude 7"),
+            ("p03a_c08", "Interlude 8"),
+            ("p03a_c09", "Interlude 9"),
+            ("end-note", "Endnote"),
+        ];
+        for (id, title) in expected {
+            assert_eq!(map_by_hand(id), Some(title), "{id}");
+            assert_eq!(pretty_chapter(BOOK, id), title, "{id}");
+        }
+        assert_eq!(map_by_hand("Day_01.html"), None);
+    }
+
+    #[test]
+    fn unrecognized_ids_are_kept() {
+        assert_eq!(pretty_chapter(BOOK, "titlepage.xhtml"), "titlepage.xhtml");
+        assert_eq!(pretty_chapter(BOOK, ""), "");
+    }
+
+    #[test]
+    fn number_words() {
+        assert_eq!(parse_number_words("twenty-three"), Some(23));
+        assert_eq!(parse_number_words("one hundred and one"), Some(101));
+        assert_eq!(parse_number_words("twentythree"), Some(23));
+        assert_eq!(parse_number_words("seventeen"), Some(17));
+        assert_eq!(parse_number_words("storm"), None);
+        assert_eq!(parse_number_words(""), None);
+    }
+
+    #[test]
+    fn roman_numerals() {
+        assert_eq!(parse_roman("IV"), Some(4));
+        assert_eq!(parse_roman("xiv"), Some(14));
+        assert_eq!(parse_roman("XLIX"), Some(49));
+        assert_eq!(parse_roman("q"), None);
+        assert_eq!(parse_roman(""), None);
+    }
+}
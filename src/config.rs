@@ -0,0 +1,169 @@
+use std::{borrow::Cow, error::Error, fs, iter, path::Path};
+
+use serde::Deserialize;
+
+use crate::{FieldMapping, Replacement};
+
+/// Where the book config is read from when nothing else is asked for.
+pub const DEFAULT_BOOKS_PATH: &str = "books.toml";
+/// Used when there's no config on disk so the binary still works on its own.
+const BUILTIN_BOOKS: &str = include_str!("../books.toml");
+
+/// A book we know how to index, and which spine indices hold its chapters.
+///
+/// ePubs are matched to books by [`find_book`].
+///
+/// The chapter indices can be left out, in which case they're guessed from
+/// the ePub's spine with [`detect_chapter_range`](crate::detect_chapter_range).
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct IndexableBook {
+    pub title: String,
+    /// Other titles the ePub might go by, like a collection it's published in.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// The ePub's `dc:identifier`, for matching one edition exactly.
+    pub epub_identifier: Option<String>,
+    pub first_chapter_index: Option<usize>,
+    pub last_chapter_index: Option<usize>,
+    #[serde(default)]
+    pub skippable_chapters: Vec<usize>,
+    /// Spine indices never skipped as part title pages, for the chapters
+    /// that guess gets wrong.
+    #[serde(default)]
+    pub keep_chapters: Vec<usize>,
+    /// Chapters open with an epigraph that should get its own record rather
+    /// than being mixed in with the body text.
+    #[serde(default)]
+    pub has_epigraphs: bool,
+    /// Cleanups for this book's HTML, run after the global ones.
+    #[serde(default)]
+    pub replacements: Vec<Replacement>,
+    /// Lines that mark a scene break in this edition, on top of the global ones.
+    #[serde(default)]
+    pub scene_borders: Vec<String>,
+    /// Overrides `--keep-links` for this book.
+    pub keep_links: Option<bool>,
+    /// Overrides `--small-caps` for this book.
+    pub small_caps: Option<bool>,
+    /// The separate works in an omnibus or collection ePub, each parsed as a
+    /// book of its own in place of this one's chapter range.
+    #[serde(default)]
+    pub works: Vec<Work>,
+    /// The table of contents entry a work's chapters are found under when
+    /// its range is left out; set by [`IndexableBook::works`].
+    #[serde(skip)]
+    pub toc_entry: Option<String>,
+}
+
+/// One work inside an omnibus ePub, like a novella in Arcanum Unbounded. Its
+/// range can't be guessed from the spine, so any end left out is read from
+/// the table of contents instead: from the entry labelled with `toc_label`
+/// (the title by default) up to the next entry that isn't nested under it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Work {
+    pub title: String,
+    pub toc_label: Option<String>,
+    pub first_chapter_index: Option<usize>,
+    pub last_chapter_index: Option<usize>,
+    #[serde(default)]
+    pub skippable_chapters: Vec<usize>,
+}
+
+impl IndexableBook {
+    /// The books to parse out of an ePub matched to this one: itself, or one
+    /// for each of its works. A work shares everything else with this book,
+    /// and takes its title and aliases as aliases, so they're still dropped
+    /// as page headers.
+    pub fn works(&self) -> Vec<Cow<'_, Self>> {
+        if self.works.is_empty() {
+            return vec![Cow::Borrowed(self)];
+        }
+        self.works
+            .iter()
+            .map(|work| {
+                Cow::Owned(Self {
+                    title: work.title.clone(),
+                    aliases: iter::once(&self.title)
+                        .chain(&self.aliases)
+                        .cloned()
+                        .collect(),
+                    first_chapter_index: work.first_chapter_index,
+                    last_chapter_index: work.last_chapter_index,
+                    skippable_chapters: work.skippable_chapters.clone(),
+                    works: Vec::new(),
+                    toc_entry: Some(work.toc_label.as_ref().unwrap_or(&work.title).clone()),
+                    ..self.clone()
+                })
+            })
+            .collect()
+    }
+}
+
+/// Everything in a `books.toml`: the books plus settings that apply to all of them.
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    #[serde(rename = "book")]
+    pub books: Vec<IndexableBook>,
+    /// Lines that mark a scene break, on top of the built-in ones.
+    #[serde(default)]
+    pub scene_borders: Vec<String>,
+    /// Prefixes of rendered lines to drop, on top of the built-in ones.
+    #[serde(default)]
+    pub ignorable_prefixes: Vec<String>,
+    /// Regexes for rendered lines to drop.
+    #[serde(default)]
+    pub ignorable_patterns: Vec<String>,
+    /// How paragraphs are joined into `display_text`, in place of the
+    /// built-in HTML.
+    pub display_template: Option<String>,
+    /// Renames and leaves out fields of the written records.
+    #[serde(default)]
+    pub fields: FieldMapping,
+    /// Cleanups for every book's HTML, on top of the built-in ones.
+    #[serde(default, rename = "replacement")]
+    pub replacements: Vec<Replacement>,
+}
+
+/// Reads a TOML config file.
+pub fn load_config(path: &Path) -> Result<Config, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+}
+
+/// The config compiled into the binary.
+pub fn builtin_config() -> Config {
+    toml::from_str(BUILTIN_BOOKS).expect("The built-in books.toml must be valid")
+}
+
+/// Finds the configured book an ePub should be parsed as.
+///
+/// A book whose `epub_identifier` matches wins outright. After that comes a
+/// title or alias equal to the ePub's title, ignoring case, and last a title
+/// or alias that appears anywhere in it.
+pub fn find_book<'a>(
+    books: &'a [IndexableBook],
+    epub_title: &str,
+    epub_identifier: Option<&str>,
+) -> Option<&'a IndexableBook> {
+    let epub_title = epub_title.to_lowercase();
+    let names = |book: &'a IndexableBook| {
+        iter::once(&book.title)
+            .chain(&book.aliases)
+            .map(|it| it.to_lowercase())
+    };
+    books
+        .iter()
+        .find(|book| {
+            epub_identifier.is_some() && book.epub_identifier.as_deref() == epub_identifier
+        })
+        .or_else(|| {
+            books
+                .iter()
+                .find(|book| names(book).any(|name| name == epub_title))
+        })
+        .or_else(|| {
+            books
+                .iter()
+                .find(|book| names(book).any(|name| epub_title.contains(&name)))
+        })
+}
@@ -0,0 +1,76 @@
+//! Pulling each book's cover image and basic metadata out for a library view.
+
+use std::{
+    error::Error,
+    fs,
+    io::{Read, Seek},
+    path::Path,
+};
+
+use epub::doc::EpubDoc;
+use serde::Serialize;
+use tracing::info;
+
+use crate::{BookMetadata, IndexableBook};
+
+/// One book's entry in `covers.json`.
+#[derive(Debug, Serialize)]
+pub struct CoverInfo {
+    pub book_title: String,
+    /// The cover image's file name inside the covers directory; unset when
+    /// the ePub has no cover.
+    pub cover_file: Option<String>,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    pub language: Option<String>,
+}
+
+/// Writes the ePub's cover into `dir` as `<book title>.<ext>`, returning what
+/// goes into `covers.json` for it.
+pub fn extract_cover<R: Read + Seek>(
+    book: &IndexableBook,
+    doc: &mut EpubDoc<R>,
+    dir: &Path,
+) -> Result<CoverInfo, Box<dyn Error>> {
+    let metadata = BookMetadata::read(doc);
+    let cover_file = match doc.get_cover() {
+        Some((image, mime)) => {
+            let file_name = format!("{}.{}", file_stem(&book.title), extension(&mime));
+            fs::write(dir.join(&file_name), image)?;
+            Some(file_name)
+        }
+        None => {
+            info!("{} has no cover", book.title);
+            None
+        }
+    };
+    Ok(CoverInfo {
+        book_title: book.title.clone(),
+        cover_file,
+        author: metadata.author,
+        series: metadata.series,
+        language: metadata.language,
+    })
+}
+
+/// A book title with the characters file systems object to swapped out.
+fn file_stem(title: &str) -> String {
+    title
+        .chars()
+        .map(|it| match it {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            other => other,
+        })
+        .collect()
+}
+
+fn extension(mime: &str) -> &str {
+    match mime {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        _ => "img",
+    }
+}
@@ -0,0 +1,161 @@
+use std::{fmt, sync::Arc};
+
+use html2text::{
+    from_read_with_decorator,
+    render::text_renderer::{TaggedLine, TextDecorator},
+};
+
+use crate::ParseOptions;
+
+/// Picks the [`TextDecorator`] chapters are rendered with, made afresh for
+/// each chapter from the options in effect for its book. The default is
+/// [`MyDecorator`], keeping links when [`ParseOptions::keep_links`] says to.
+///
+/// Whatever markup a decorator adds ends up in `display_text`, and in
+/// `searchable_text` too unless it's an `<em>`, `<a>`, or `<span>` tag.
+#[derive(Clone)]
+pub struct DecoratorFactory(Arc<Render>);
+
+/// Renders a chapter's HTML with whatever decorator the options call for.
+type Render = dyn Fn(&[u8], &ParseOptions) -> String + Send + Sync;
+
+impl DecoratorFactory {
+    /// Renders with whatever `make_decorator` builds from each chapter's options.
+    pub fn new<D: TextDecorator + 'static>(
+        make_decorator: impl Fn(&ParseOptions) -> D + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(move |html, options| {
+            from_read_with_decorator(html, options.render_width, make_decorator(options))
+        }))
+    }
+
+    /// `html` rendered to text at [`ParseOptions::render_width`].
+    pub(crate) fn render(&self, html: &[u8], options: &ParseOptions) -> String {
+        (self.0)(html, options)
+    }
+}
+
+impl Default for DecoratorFactory {
+    fn default() -> Self {
+        Self::new(|options| MyDecorator::with_links(options.keep_links))
+    }
+}
+
+impl fmt::Debug for DecoratorFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DecoratorFactory(..)")
+    }
+}
+
+/// Renders chapter HTML down to plain text, keeping only the markup that
+/// `display_text` needs (emphasis and strikeout, plus links if asked for).
+#[derive(Clone, Debug)]
+pub struct MyDecorator {
+    keep_links: bool,
+}
+
+impl MyDecorator {
+    pub const fn new() -> Self {
+        Self::with_links(false)
+    }
+
+    /// A decorator that, when `keep_links` is set, wraps linked text in
+    /// `<a href="...">` with the href exactly as it appears in the ePub.
+    pub const fn with_links(keep_links: bool) -> Self {
+        Self { keep_links }
+    }
+}
+
+impl TextDecorator for MyDecorator {
+    type Annotation = ();
+
+    fn decorate_code_start(&mut self) -> (String, Self::Annotation) {
+        (String::new(), ())
+    }
+
+    fn decorate_code_end(&mut self) -> String {
+        String::new()
+    }
+
+    fn decorate_em_start(&mut self) -> (String, Self::Annotation) {
+        (String::from("<em>"), ())
+    }
+
+    fn decorate_em_end(&mut self) -> String {
+        String::from("</em>")
+    }
+
+    fn decorate_image(&mut self, _src: &str, title: &str) -> (String, Self::Annotation) {
+        // html2text hands over the image's alt text as the title
+        (title.to_owned(), ())
+    }
+
+    fn decorate_link_start(&mut self, url: &str) -> (String, Self::Annotation) {
+        if self.keep_links {
+            (format!("<a href=\"{}\">", escape_attribute(url)), ())
+        } else {
+            (String::new(), ())
+        }
+    }
+
+    fn decorate_link_end(&mut self) -> String {
+        if self.keep_links {
+            String::from("</a>")
+        } else {
+            String::new()
+        }
+    }
+
+    fn decorate_preformat_first(&mut self) -> Self::Annotation {}
+
+    fn decorate_preformat_cont(&mut self) -> Self::Annotation {}
+
+    fn decorate_strikeout_start(&mut self) -> (String, Self::Annotation) {
+        (String::from("<s>"), ())
+    }
+
+    fn decorate_strikeout_end(&mut self) -> String {
+        String::from("</s>")
+    }
+
+    fn decorate_strong_start(&mut self) -> (String, Self::Annotation) {
+        (String::new(), ())
+    }
+
+    fn decorate_strong_end(&mut self) -> String {
+        String::new()
+    }
+
+    fn header_prefix(&mut self, _level: usize) -> String {
+        String::new()
+    }
+
+    fn quote_prefix(&mut self) -> String {
+        String::new()
+    }
+
+    fn ordered_item_prefix(&mut self, _i: i64) -> String {
+        String::new()
+    }
+
+    fn unordered_item_prefix(&mut self) -> String {
+        String::new()
+    }
+
+    fn finalise(&mut self, _links: Vec<String>) -> Vec<TaggedLine<Self::Annotation>> {
+        Vec::new()
+    }
+
+    fn make_subblock_decorator(&self) -> Self {
+        Self::with_links(self.keep_links)
+    }
+}
+
+/// `value` made safe to put between the double quotes of an HTML attribute.
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
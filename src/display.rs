@@ -0,0 +1,110 @@
+//! Joining a paragraph and its neighbors into `display_text`.
+
+/// What `display_text` looks like when nothing else is configured: the
+/// paragraphs joined as HTML, for the page around it to wrap in `<p>` tags.
+pub const DEFAULT_DISPLAY_TEMPLATE: &str = "{prev}</p><p>{curr}</p><p>{next}";
+
+/// A `display_text` template like [`DEFAULT_DISPLAY_TEMPLATE`], split up around
+/// its `{prev}`, `{curr}`, and `{next}` placeholders.
+///
+/// The text between `{prev}` and `{curr}` separates each earlier paragraph
+/// from the one after it, and likewise for `{curr}` and `{next}`. A separator
+/// is only written where there's a paragraph on both sides of it, so a record
+/// at a scene break doesn't start or end with one. Leaving `{prev}` or `{next}`
+/// out leaves those paragraphs out.
+#[derive(Clone, Debug)]
+pub(crate) struct DisplayTemplate {
+    prefix: String,
+    before_separator: Option<String>,
+    after_separator: Option<String>,
+    suffix: String,
+}
+
+impl DisplayTemplate {
+    pub(crate) fn parse(template: &str) -> Result<Self, String> {
+        let Some((before, after)) = split_around(template, "{curr}")? else {
+            return Err(format!("display template {template:?} has no {{curr}}"));
+        };
+        let (prefix, before_separator) = match split_around(before, "{prev}")? {
+            Some((prefix, separator)) => (prefix, Some(separator.to_string())),
+            None => (before, None),
+        };
+        let (after_separator, suffix) = match split_around(after, "{next}")? {
+            Some((separator, suffix)) => (Some(separator.to_string()), suffix),
+            None => (None, after),
+        };
+        let pieces = [
+            Some(prefix),
+            before_separator.as_deref(),
+            after_separator.as_deref(),
+            Some(suffix),
+        ];
+        if pieces
+            .into_iter()
+            .flatten()
+            .any(|it| it.contains("{prev}") || it.contains("{next}"))
+        {
+            return Err(format!(
+                "display template {template:?} needs {{prev}}, {{curr}}, and {{next}} in that order"
+            ));
+        }
+        Ok(Self {
+            prefix: prefix.to_string(),
+            before_separator,
+            after_separator,
+            suffix: suffix.to_string(),
+        })
+    }
+
+    /// `curr` with the paragraphs before and after it, in reading order.
+    pub(crate) fn render<'a>(
+        &self,
+        before: impl IntoIterator<Item = &'a str>,
+        curr: &str,
+        after: impl IntoIterator<Item = &'a str>,
+    ) -> String {
+        let mut out = self.prefix.clone();
+        if let Some(separator) = &self.before_separator {
+            for paragraph in before {
+                out.push_str(paragraph);
+                out.push_str(separator);
+            }
+        }
+        out.push_str(curr);
+        if let Some(separator) = &self.after_separator {
+            for paragraph in after {
+                out.push_str(separator);
+                out.push_str(paragraph);
+            }
+        }
+        out.push_str(&self.suffix);
+        out
+    }
+
+    /// Paragraphs that belong together, like an epigraph's lines, joined by
+    /// the template's separator.
+    pub(crate) fn join<'a>(&self, paragraphs: impl IntoIterator<Item = &'a str>) -> String {
+        let separator = self
+            .after_separator
+            .as_ref()
+            .or(self.before_separator.as_ref())
+            .map_or(" ", String::as_str);
+        let body = paragraphs.into_iter().collect::<Vec<_>>().join(separator);
+        format!("{}{body}{}", self.prefix, self.suffix)
+    }
+}
+
+/// The text on either side of `placeholder` in `text`, if it's there. It can
+/// only be there once.
+fn split_around<'a>(
+    text: &'a str,
+    placeholder: &str,
+) -> Result<Option<(&'a str, &'a str)>, String> {
+    match text.split(placeholder).collect::<Vec<_>>()[..] {
+        [_] => Ok(None),
+        [before, after] => Ok(Some((before, after))),
+        _ => Err(format!(
+            "display template {text:?} has more than one {placeholder}"
+        )),
+    }
+}
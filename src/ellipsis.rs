@@ -0,0 +1,104 @@
+//! Spelling every ellipsis the same way, so phrase searches across one match
+//! whichever way the ePub typeset it.
+
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+
+/// Turns every spelling of an ellipsis in a rendered line, `...`, `. . .`,
+/// `. . . .`, dots spaced with non-breaking spaces, and `…` itself, into a
+/// single `…`.
+///
+/// Any space before it is dropped, and in the middle of a sentence it's
+/// followed by exactly one space: `I don't . . .know` becomes `I don't… know`.
+/// One that opens a line or a quote stays against the word after it, and one
+/// before closing punctuation stays against that.
+pub(crate) fn normalize_ellipses(line: &str) -> String {
+    static ELLIPSIS: OnceLock<Regex> = OnceLock::new();
+    let ellipsis = ELLIPSIS.get_or_init(|| {
+        // Three or more dots, each maybe after a space, or a `…`, then any
+        // stray dots like the period of a four-dot ellipsis. The spaces around
+        // it are taken too so they can be put back the same way every time.
+        Regex::new(r"[ \u{a0}\u{202f}]*(?:…|\.(?:[ \u{a0}\u{202f}]?\.){2,})(?:[ \u{a0}\u{202f}]?\.)*[ \u{a0}\u{202f}]*")
+            .expect("valid regex")
+    });
+    ellipsis
+        .replace_all(line, |caps: &Captures| {
+            let whole = caps.get(0).expect("the whole match is always there");
+            let before = line[..whole.start()].chars().next_back();
+            let after = line[whole.end()..].chars().next();
+            match (before, after) {
+                (Some(before), Some(after)) if !is_opening(before) && !is_closing(after) => "… ",
+                _ => "…",
+            }
+        })
+        .into_owned()
+}
+
+/// Punctuation an ellipsis can come straight after, like the start of a quote.
+fn is_opening(c: char) -> bool {
+    matches!(c, '“' | '‘' | '"' | '(' | '[' | '—')
+}
+
+/// Punctuation that closes up against an ellipsis before it.
+fn is_closing(c: char) -> bool {
+    matches!(
+        c,
+        '”' | '’' | '"' | '\'' | ')' | ']' | '?' | '!' | ',' | ';' | ':' | '—'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_ellipses;
+
+    #[test]
+    fn three_ascii_periods() {
+        assert_eq!(normalize_ellipses("Wait... what?"), "Wait… what?");
+    }
+
+    #[test]
+    fn spaced_periods() {
+        assert_eq!(normalize_ellipses("I don't . . . know."), "I don't… know.");
+    }
+
+    #[test]
+    fn four_dots_at_the_end_of_a_sentence() {
+        assert_eq!(normalize_ellipses("It ended. . . . Then"), "It ended… Then");
+        assert_eq!(normalize_ellipses("It ended...."), "It ended…");
+    }
+
+    #[test]
+    fn non_breaking_spaces_between_the_dots() {
+        assert_eq!(
+            normalize_ellipses("So\u{a0}.\u{a0}.\u{a0}. cold"),
+            "So… cold"
+        );
+        assert_eq!(
+            normalize_ellipses("So\u{202f}.\u{202f}.\u{202f}.\u{202f}cold"),
+            "So… cold"
+        );
+    }
+
+    #[test]
+    fn spacing_around_an_ellipsis_character() {
+        assert_eq!(normalize_ellipses("Well …"), "Well…");
+        assert_eq!(normalize_ellipses("Well …  maybe"), "Well… maybe");
+        assert_eq!(normalize_ellipses("Well…maybe"), "Well… maybe");
+        assert_eq!(normalize_ellipses("Well… maybe"), "Well… maybe");
+    }
+
+    #[test]
+    fn against_quotes_and_punctuation() {
+        assert_eq!(normalize_ellipses("“... and then”"), "“…and then”");
+        assert_eq!(normalize_ellipses("“And then . . .”"), "“And then…”");
+        assert_eq!(normalize_ellipses("Really...?"), "Really…?");
+        assert_eq!(normalize_ellipses("...and so"), "…and so");
+    }
+
+    #[test]
+    fn leaves_other_dots_alone() {
+        assert_eq!(normalize_ellipses("Mr. Harrow. Two.."), "Mr. Harrow. Two..");
+        assert_eq!(normalize_ellipses("No ellipsis here."), "No ellipsis here.");
+    }
+}
@@ -0,0 +1,78 @@
+//! Decoding chapters whose HTML isn't valid UTF-8, so one oddly encoded file
+//! still gets indexed instead of failing its whole book.
+
+use std::{borrow::Cow, sync::OnceLock};
+
+use regex::Regex;
+use tracing::warn;
+
+/// How far into a chapter to look for an encoding declaration.
+const DECLARATION_WINDOW: usize = 1024;
+
+/// `html` as text. It's read as UTF-8 when it can be; otherwise as the
+/// encoding it declares, if that's Latin-1 or Windows-1252, and failing that
+/// with the bad bytes swapped for U+FFFD. Either fallback is logged, naming
+/// `spine_id`.
+pub(crate) fn decode_chapter<'a>(html: &'a [u8], spine_id: &str) -> Cow<'a, str> {
+    let why = match std::str::from_utf8(html) {
+        Ok(text) => return Cow::Borrowed(text),
+        Err(why) => why,
+    };
+    match declared_encoding(html) {
+        Some(label) if is_windows_1252(&label) => {
+            warn!("Chapter {spine_id} isn't UTF-8 ({why}), reading it as its declared {label}");
+            Cow::Owned(html.iter().copied().map(windows_1252_char).collect())
+        }
+        _ => {
+            warn!("Chapter {spine_id} isn't valid UTF-8 ({why}), replacing the bad bytes");
+            String::from_utf8_lossy(html)
+        }
+    }
+}
+
+/// The encoding named by the XML declaration or a `<meta>` charset near the
+/// start of `html`, lowercased.
+fn declared_encoding(html: &[u8]) -> Option<String> {
+    static DECLARATION: OnceLock<Regex> = OnceLock::new();
+    let declaration = DECLARATION.get_or_init(|| {
+        Regex::new(r#"(?i)(?:<\?xml[^>]*?\bencoding|<meta[^>]*?\bcharset)\s*=\s*["']?([\w.:-]+)"#)
+            .expect("valid regex")
+    });
+    // Declarations are ASCII, so cutting a character in half here is harmless
+    let head = String::from_utf8_lossy(&html[..html.len().min(DECLARATION_WINDOW)]);
+    let label = declaration.captures(&head)?.get(1)?.as_str();
+    Some(label.to_ascii_lowercase())
+}
+
+/// Whether `label` names Windows-1252 or one of the encodings decoded as it,
+/// the way browsers do for Latin-1.
+fn is_windows_1252(label: &str) -> bool {
+    matches!(
+        label,
+        "windows-1252"
+            | "cp1252"
+            | "x-cp1252"
+            | "iso-8859-1"
+            | "iso8859-1"
+            | "iso_8859-1"
+            | "latin1"
+            | "l1"
+            | "us-ascii"
+            | "ascii"
+    )
+}
+
+/// The character `byte` stands for in Windows-1252. It matches Latin-1 apart
+/// from 0x80 to 0x9F, which hold curly quotes, dashes, and the like; the five
+/// bytes it leaves unassigned map to the control characters of the same value.
+fn windows_1252_char(byte: u8) -> char {
+    const HIGH_CONTROLS: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž',
+        '\u{8f}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}',
+        'ž', 'Ÿ',
+    ];
+    match byte {
+        0x80..=0x9f => HIGH_CONTROLS[usize::from(byte - 0x80)],
+        _ => char::from(byte),
+    }
+}
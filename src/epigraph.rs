@@ -0,0 +1,114 @@
+//! Finding the in-world quote that opens a chapter, like the death rattles
+//! in the Stormlight books.
+
+use std::{iter::Peekable, sync::OnceLock};
+
+use regex::Regex;
+
+use crate::html::attribute;
+
+/// Put at the start of paragraphs whose CSS class marks them as an epigraph
+/// so the mark survives rendering.
+const EPIGRAPH_MARKER: char = '\u{E002}';
+
+/// How far into a chapter the epigraph can start, leaving room for headings.
+const MAX_LINES_BEFORE_EPIGRAPH: usize = 3;
+
+/// Why a line looks like part of an epigraph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EpigraphHint {
+    /// Its CSS class says so.
+    Class,
+    /// The whole line is italic, which an italic thought can be too.
+    Italic,
+}
+
+/// Marks every paragraph or block with an `epigraph` CSS class.
+pub fn mark_epigraph_paragraphs(html: &str) -> String {
+    static BLOCK_TAG: OnceLock<Regex> = OnceLock::new();
+    let block_tag = BLOCK_TAG
+        .get_or_init(|| Regex::new(r"(?i)<(?:p|div|blockquote)\b[^>]*>").expect("valid regex"));
+    block_tag
+        .replace_all(html, |caps: &regex::Captures| {
+            let class = attribute(&caps[0], "class").unwrap_or_default();
+            if class.to_lowercase().contains("epigraph") {
+                format!("{}{EPIGRAPH_MARKER}", &caps[0])
+            } else {
+                caps[0].to_owned()
+            }
+        })
+        .into_owned()
+}
+
+/// Strips the epigraph mark from a rendered line, returning the clean line and
+/// why it looks like part of an epigraph, if it does: either it was marked by
+/// class or the whole line is italic.
+pub(crate) fn take_epigraph_mark(line: &str) -> (String, Option<EpigraphHint>) {
+    if let Some(rest) = line.trim_start().strip_prefix(EPIGRAPH_MARKER) {
+        return (rest.to_owned(), Some(EpigraphHint::Class));
+    }
+    let fully_italic = line
+        .trim()
+        .strip_prefix("<em>")
+        .and_then(|it| it.strip_suffix("</em>"))
+        .is_some_and(|inner| !inner.contains("</em>"));
+    (
+        line.to_owned(),
+        fully_italic.then_some(EpigraphHint::Italic),
+    )
+}
+
+/// Takes just enough lines off the top of a chapter for [`epigraph_range`]
+/// to give the same answer it would for the whole chapter.
+pub(crate) fn leading_lines<T, I: Iterator<Item = T>>(
+    lines: &mut Peekable<I>,
+    is_epigraph: impl Fn(&T) -> bool,
+) -> Vec<T> {
+    let mut head: Vec<T> = Vec::new();
+    while let Some(line) = lines.next_if(|next| {
+        head.len() <= MAX_LINES_BEFORE_EPIGRAPH
+            // Don't cut off an epigraph that runs past where it has to start
+            || (head.last().is_some_and(&is_epigraph) && is_epigraph(next))
+    }) {
+        head.push(line);
+    }
+    head
+}
+
+/// Where the epigraph sits in a chapter's lines, given which lines look like
+/// one: the first run of such lines starting near the top. A run that's only
+/// italic has to open the chapter or follow a line of its title, since one
+/// after some prose is more likely a character's thought.
+pub(crate) fn epigraph_range(
+    hints: &[Option<EpigraphHint>],
+    is_title: impl Fn(usize) -> bool,
+) -> Option<std::ops::Range<usize>> {
+    let start = hints
+        .iter()
+        .take(MAX_LINES_BEFORE_EPIGRAPH + 1)
+        .enumerate()
+        .position(|(i, hint)| match hint {
+            Some(EpigraphHint::Class) => true,
+            Some(EpigraphHint::Italic) => i == 0 || is_title(i - 1),
+            None => false,
+        })?;
+    let len = hints[start..].iter().take_while(|it| it.is_some()).count();
+    Some(start..start + len)
+}
+
+/// Splits the attribution off the end of an epigraph's plain-text lines: a
+/// last line starting with an em or en dash, like `—From the Ars Arcanum`.
+/// The attribution comes back without its dash.
+pub(crate) fn split_attribution(lines: &[String]) -> (&[String], Option<String>) {
+    match lines.split_last() {
+        Some((last, quote)) if !quote.is_empty() => {
+            match last.trim_start().strip_prefix(['—', '–']) {
+                Some(source) if !source.trim().is_empty() => {
+                    (quote, Some(source.trim().to_owned()))
+                }
+                _ => (lines, None),
+            }
+        }
+        _ => (lines, None),
+    }
+}
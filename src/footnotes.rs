@@ -0,0 +1,110 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
+};
+
+use html2text::from_read_with_decorator;
+use regex::{Captures, Regex};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::MyDecorator;
+
+/// Wrapped around a footnote id where the reference sat in the text, so it
+/// survives rendering and can be matched back up to its paragraph.
+const FOOTNOTE_REF_START: char = '\u{E000}';
+const FOOTNOTE_REF_END: char = '\u{E001}';
+
+/// Pulls the footnote bodies out of a chapter.
+///
+/// Returns the chapter with each footnote `<aside>`/`<div>` removed and each
+/// link to one replaced by a marker for [`take_footnote_refs`], plus the
+/// rendered footnote text by id. Only elements that something in the chapter
+/// links to count as footnotes.
+pub fn extract_footnotes(html: &str, file_name: &str) -> (String, HashMap<String, String>) {
+    static LINK: OnceLock<Regex> = OnceLock::new();
+    static ASIDE: OnceLock<Regex> = OnceLock::new();
+    static DIV: OnceLock<Regex> = OnceLock::new();
+    let link = LINK.get_or_init(|| {
+        Regex::new(r##"(?s)<a\b[^>]*?\bhref="#([^"]+)"[^>]*>.*?</a>"##).expect("valid regex")
+    });
+    let aside = ASIDE.get_or_init(|| {
+        Regex::new(r#"(?s)<aside\b[^>]*?\bid="([^"]+)"[^>]*>(.*?)</aside>"#).expect("valid regex")
+    });
+    let div = DIV.get_or_init(|| {
+        Regex::new(r#"(?s)<div\b[^>]*?\bid="([^"]+)"[^>]*>(.*?)</div>"#).expect("valid regex")
+    });
+
+    // Links back into this same file are written either way
+    let html = if file_name.is_empty() {
+        html.to_owned()
+    } else {
+        html.replace(&format!("href=\"{file_name}#"), "href=\"#")
+    };
+    let linked_ids: HashSet<String> = link
+        .captures_iter(&html)
+        .map(|it| it[1].to_owned())
+        .collect();
+
+    let mut footnotes = HashMap::new();
+    let mut take_body = |caps: &Captures| {
+        if linked_ids.contains(&caps[1]) {
+            footnotes.insert(caps[1].to_owned(), render_footnote(&caps[2]));
+            String::new()
+        } else {
+            caps[0].to_owned()
+        }
+    };
+    let html = aside.replace_all(&html, &mut take_body).into_owned();
+    let html = div.replace_all(&html, &mut take_body).into_owned();
+
+    let html = link
+        .replace_all(&html, |caps: &Captures| {
+            if footnotes.contains_key(&caps[1]) {
+                format!("{FOOTNOTE_REF_START}{}{FOOTNOTE_REF_END}", &caps[1])
+            } else {
+                caps[0].to_owned()
+            }
+        })
+        .into_owned();
+    (html, footnotes)
+}
+
+/// Strips the footnote markers out of a rendered line, returning the clean
+/// line and the ids it referenced.
+pub fn take_footnote_refs(line: &str) -> (String, Vec<String>) {
+    let mut text = String::with_capacity(line.len());
+    let mut ids = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find(FOOTNOTE_REF_START) {
+        text.push_str(&rest[..start]);
+        let after_start = &rest[start + FOOTNOTE_REF_START.len_utf8()..];
+        let Some(end) = after_start.find(FOOTNOTE_REF_END) else {
+            rest = after_start;
+            break;
+        };
+        ids.push(after_start[..end].to_owned());
+        rest = &after_start[end + FOOTNOTE_REF_END.len_utf8()..];
+    }
+    text.push_str(rest);
+    (text, ids)
+}
+
+/// Renders a footnote body to a single line of plain text, dropping the
+/// backlink to the reference.
+fn render_footnote(body: &str) -> String {
+    static BACKLINK: OnceLock<Regex> = OnceLock::new();
+    let backlink = BACKLINK.get_or_init(|| {
+        Regex::new(r#"(?s)<a\b[^>]*?\bhref="[^"]*#[^"]*"[^>]*>.*?</a>"#).expect("valid regex")
+    });
+    let body = backlink.replace_all(body, "");
+    from_read_with_decorator(body.as_bytes(), usize::MAX, MyDecorator::new())
+        .lines()
+        .map(str::trim)
+        .filter(|it| !it.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("<em>", "")
+        .replace("</em>", "")
+        .nfc()
+        .collect()
+}
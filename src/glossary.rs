@@ -0,0 +1,41 @@
+//! Picking out the italicized terms in a chapter, like ship names and forms
+//! of magic, as a start on a glossary of the book.
+
+use std::{collections::HashSet, sync::OnceLock};
+
+use regex::Regex;
+
+use crate::strip_markup;
+
+/// Italics longer than this many words are taken for thoughts, emphasis, or
+/// quoted writing rather than a term, as are whole italic paragraphs like
+/// epigraphs.
+const MAX_TERM_WORDS: usize = 4;
+
+/// The distinct italicized spans in a chapter's rendered `lines`, in the
+/// order they first appear, without the punctuation around them. Spans that
+/// differ only in case are the same term.
+pub(crate) fn glossary_candidates<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    static EM: OnceLock<Regex> = OnceLock::new();
+    let em = EM.get_or_init(|| Regex::new(r"(?s)<em>(.*?)</em>").expect("valid regex"));
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    let trim = |text: &str| {
+        text.trim_matches(|it: char| !it.is_alphanumeric())
+            .to_string()
+    };
+    for line in lines {
+        let whole_line = trim(&strip_markup(line, false));
+        for caps in em.captures_iter(line) {
+            let term = trim(&strip_markup(&caps[1], false));
+            let words = term.split_whitespace().count();
+            if words == 0 || words > MAX_TERM_WORDS || term == whole_line {
+                continue;
+            }
+            if seen.insert(term.to_lowercase()) {
+                candidates.push(term);
+            }
+        }
+    }
+    candidates
+}
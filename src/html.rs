@@ -0,0 +1,510 @@
+//! Passes over a chapter's raw HTML that run before it's rendered to text.
+
+use std::sync::OnceLock;
+
+use regex::{Captures, NoExpand, Regex};
+use serde::Deserialize;
+
+/// What scene-break ornaments are rewritten to; always one of the default
+/// scene borders.
+pub const SCENE_BREAK_MARKER: &str = "* * *";
+
+/// Bits of an image's file name or an element's CSS class that mean it's a
+/// scene-break ornament rather than an illustration or text.
+const SCENE_BREAK_PATTERNS: [&str; 7] = [
+    "break",
+    "scene",
+    "divider",
+    "separator",
+    "ornament",
+    "dinkus",
+    "fleuron",
+];
+
+/// Brackets an illustration's description through rendering, so it can be
+/// moved out of the text afterwards.
+const IMAGE_START: char = '\u{E005}';
+const IMAGE_END: char = '\u{E006}';
+
+/// Replaces `<img>` tags that are scene-break ornaments with
+/// [`SCENE_BREAK_MARKER`], and every other image with its `alt` text (or
+/// `title`, failing that) marked for [`take_images`]. Images with neither are
+/// dropped.
+pub fn mark_images(html: &str) -> String {
+    static IMG: OnceLock<Regex> = OnceLock::new();
+    let img = IMG.get_or_init(|| Regex::new(r"(?i)<img\b[^>]*>").expect("valid regex"));
+    img.replace_all(html, |caps: &Captures| {
+        let tag = &caps[0];
+        let src = attribute(tag, "src").unwrap_or_default();
+        let file_name = src.rsplit('/').next().unwrap_or_default();
+        let class = attribute(tag, "class").unwrap_or_default();
+        let names = format!("{file_name} {class}").to_lowercase();
+        if SCENE_BREAK_PATTERNS
+            .iter()
+            .any(|pattern| names.contains(pattern))
+        {
+            return SCENE_BREAK_MARKER.to_owned();
+        }
+        match [attribute(tag, "alt"), attribute(tag, "title")]
+            .into_iter()
+            .flatten()
+            .map(str::trim)
+            .find(|it| !it.is_empty())
+        {
+            Some(description) => format!("{IMAGE_START}{description}{IMAGE_END}"),
+            None => String::new(),
+        }
+    })
+    .into_owned()
+}
+
+/// Strips the image descriptions marked by [`mark_images`] out of a rendered
+/// line, returning the rest of the line and the descriptions. A line that was
+/// nothing but images, like a map on its own page, keeps the descriptions as
+/// its text so it's still searchable.
+pub fn take_images(line: &str) -> (String, Vec<String>) {
+    let mut text = String::with_capacity(line.len());
+    let mut images = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find(IMAGE_START) {
+        text.push_str(&rest[..start]);
+        let after_start = &rest[start + IMAGE_START.len_utf8()..];
+        let Some(end) = after_start.find(IMAGE_END) else {
+            rest = after_start;
+            break;
+        };
+        images.push(after_start[..end].to_owned());
+        rest = &after_start[end + IMAGE_END.len_utf8()..];
+        if text.ends_with(' ') {
+            // Don't leave a double space where an inline image was
+            rest = rest.trim_start_matches(' ');
+        }
+    }
+    text.push_str(rest);
+    if text.trim().is_empty() && !images.is_empty() {
+        return (images.join(" "), images);
+    }
+    (text, images)
+}
+
+/// Replaces the other ways ePubs mark scene breaks with
+/// [`SCENE_BREAK_MARKER`]: `<hr>` rules, and paragraphs with no text in them
+/// that either have a scene-break CSS class or are just a centered blank line.
+/// Runs before [`mark_images`], so a centered image, which is no blank line,
+/// is told apart by its `<img>` tag.
+pub fn mark_scene_break_elements(html: &str) -> String {
+    static HR: OnceLock<Regex> = OnceLock::new();
+    static BLOCK: OnceLock<Regex> = OnceLock::new();
+    let hr = HR.get_or_init(|| Regex::new(r"(?i)<hr\b[^>]*>(\s*</hr>)?").expect("valid regex"));
+    let block = BLOCK.get_or_init(|| {
+        // The content can't hold another block, so only innermost ones match
+        Regex::new(r"(?is)<(p|div)\b([^>]*)>((?:[^<]|<[^pdPD/]|</[^pdPD])*?)</(p|div)>")
+            .expect("valid regex")
+    });
+
+    let marker = format!("<p>{SCENE_BREAK_MARKER}</p>");
+    let html = hr.replace_all(html, marker.as_str());
+    block
+        .replace_all(&html, |caps: &Captures| {
+            let class = attribute(&caps[2], "class")
+                .unwrap_or_default()
+                .to_lowercase();
+            let text = visible_text(&caps[3]);
+            let is_break = caps[1].eq_ignore_ascii_case(&caps[4])
+                && if text.trim().is_empty() {
+                    (class.contains("center") && !has_image(&caps[3]))
+                        || is_scene_break_class(&class)
+                } else {
+                    // Ornaments like `◆` or `* * *` count, words don't
+                    !text.chars().any(char::is_alphanumeric) && is_scene_break_class(&class)
+                };
+            if is_break {
+                marker.clone()
+            } else {
+                caps[0].to_owned()
+            }
+        })
+        .into_owned()
+}
+
+fn has_image(html: &str) -> bool {
+    html.to_lowercase().contains("<img")
+}
+
+fn is_scene_break_class(class: &str) -> bool {
+    SCENE_BREAK_PATTERNS
+        .iter()
+        .any(|pattern| class.contains(pattern))
+}
+
+/// Some HTML with its tags and entities dropped and non-breaking spaces
+/// turned into plain ones.
+fn visible_text(html: &str) -> String {
+    static MARKUP: OnceLock<Regex> = OnceLock::new();
+    let markup = MARKUP.get_or_init(|| Regex::new(r"<[^>]*>|&[#\w]+;").expect("valid regex"));
+    markup.replace_all(html, " ").replace('\u{a0}', " ")
+}
+
+/// The value of attribute `name` on a single tag, if it's there, in either
+/// kind of quotes and after any whitespace, line breaks included.
+pub fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut from = 0;
+    while let Some(at) = tag[from..].find(name).map(|it| it + from) {
+        from = at + name.len();
+        let follows_space = tag[..at]
+            .chars()
+            .next_back()
+            .is_some_and(|it| it.is_ascii_whitespace());
+        let Some(value) = tag[from..].strip_prefix('=') else {
+            continue;
+        };
+        let Some(quote) = value.chars().next().filter(|it| matches!(it, '"' | '\'')) else {
+            continue;
+        };
+        if follows_space {
+            let value = &value[1..];
+            return value.find(quote).map(|len| &value[..len]);
+        }
+    }
+    None
+}
+
+/// Bits of an element's CSS class that mean it holds a chapter's drop cap.
+const DROP_CAP_PATTERNS: [&str; 4] = ["drop", "initial", "firstletter", "first-letter"];
+
+/// Puts drop caps back against the rest of their word, which rendering would
+/// leave on a line of their own when they're a block, or cut off with a space
+/// when the markup is spread over lines: `<div class="dropcap">W</div><p>hen`
+/// becomes `<p>When`. An element counts as a drop cap when it holds a single
+/// capital and either has a class like `dropcap` or `initial`, or is a span
+/// opening a paragraph right before a lowercase letter. A space is kept
+/// before the next word for `A`, `I`, and `O`, which can be words by
+/// themselves, when the markup has one.
+pub fn join_drop_caps(html: &str) -> String {
+    static DROP_CAP: OnceLock<Regex> = OnceLock::new();
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    let drop_cap = DROP_CAP.get_or_init(|| {
+        // Then any whitespace and opening tags, and the letter after them
+        Regex::new(
+            r"(?is)<(span|div|p|big|b|strong|small)\b([^>]*)>\s*([“‘\x22']?[\p{Lu}\p{Lt}])\s*</(?:span|div|p|big|b|strong|small)\s*>((?:\s|<(?:p|div|span|big|b|strong|small|em|i)\b[^>]*>)*)(\p{L})",
+        )
+        .expect("valid regex")
+    });
+    let tag = TAG.get_or_init(|| Regex::new(r"<[^>]*>").expect("valid regex"));
+    drop_cap
+        .replace_all(html, |caps: &Captures| {
+            let class = attribute(&caps[2], "class")
+                .unwrap_or_default()
+                .to_lowercase();
+            let has_drop_cap_class = DROP_CAP_PATTERNS
+                .iter()
+                .any(|pattern| class.contains(pattern));
+            let start = caps
+                .get(0)
+                .expect("the whole match is always there")
+                .start();
+            let opens_paragraph = caps[1].eq_ignore_ascii_case("span")
+                && opens_block(&html[..start])
+                && caps[5].chars().all(char::is_lowercase);
+            if !has_drop_cap_class && !opens_paragraph {
+                return caps[0].to_owned();
+            }
+            let letter = &caps[3];
+            let gap = &caps[4];
+            let tags: String = tag.find_iter(gap).map(|it| it.as_str()).collect();
+            let is_word = matches!(letter.chars().last(), Some('A' | 'I' | 'O'));
+            let space = if is_word && gap.ends_with(char::is_whitespace) {
+                " "
+            } else {
+                ""
+            };
+            format!("{tags}{letter}{space}{}", &caps[5])
+        })
+        .into_owned()
+}
+
+/// Whether `html` ends with a `<p>` or `<div>` opening tag, give or take
+/// whitespace.
+fn opens_block(html: &str) -> bool {
+    let html = html.trim_end();
+    if !html.ends_with('>') {
+        return false;
+    }
+    let Some(tag) = html
+        .rfind('<')
+        .map(|it| html[it + 1..].to_ascii_lowercase())
+    else {
+        return false;
+    };
+    ["p", "div"].iter().any(|name| {
+        tag.strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with(|c: char| c == '>' || c.is_whitespace()))
+    })
+}
+
+/// Brackets small-caps text through rendering, which would otherwise drop the
+/// span around it.
+const SMALL_CAPS_START: char = '\u{E003}';
+const SMALL_CAPS_END: char = '\u{E004}';
+
+/// Rewrites the text of `ePub-SC` small-caps spans in title case, so styled
+/// words like `THE` don't turn up shouting in search results. With
+/// `keep_styling`, the text is also marked so [`restore_small_caps`] can wrap
+/// it in a `<span class="sc">` after rendering.
+pub fn mark_small_caps(html: &str, keep_styling: bool) -> String {
+    static SMALL_CAPS: OnceLock<Regex> = OnceLock::new();
+    let small_caps = SMALL_CAPS.get_or_init(|| {
+        Regex::new(r#"(?s)<span\b[^>]*\bclass=["'][^"']*\bePub-SC\b[^"']*["'][^>]*>(.*?)</span>"#)
+            .expect("valid regex")
+    });
+    small_caps
+        .replace_all(html, |caps: &Captures| {
+            let text = title_case_text(&caps[1]);
+            if keep_styling {
+                format!("{SMALL_CAPS_START}{text}{SMALL_CAPS_END}")
+            } else {
+                text
+            }
+        })
+        .into_owned()
+}
+
+/// Turns the marks left by [`mark_small_caps`] in a rendered line into
+/// `<span class="sc">` tags.
+pub fn restore_small_caps(line: &str) -> String {
+    line.replace(SMALL_CAPS_START, "<span class=\"sc\">")
+        .replace(SMALL_CAPS_END, "</span>")
+}
+
+/// Brackets a paragraph's CSS class through rendering, at the start of its
+/// text.
+const CLASS_START: char = '\u{E007}';
+const CLASS_END: char = '\u{E008}';
+
+/// Marks every `<p>` that has a CSS class, like `Letter` or `Song`, with it
+/// for [`take_paragraph_class`].
+pub fn mark_paragraph_classes(html: &str) -> String {
+    static P: OnceLock<Regex> = OnceLock::new();
+    let p = P.get_or_init(|| Regex::new(r"(?i)<p\b[^>]*>").expect("valid regex"));
+    p.replace_all(html, |caps: &Captures| {
+        let tag = &caps[0];
+        match attribute(tag, "class").map(str::trim) {
+            Some(class) if !class.is_empty() => format!("{tag}{CLASS_START}{class}{CLASS_END}"),
+            _ => tag.to_owned(),
+        }
+    })
+    .into_owned()
+}
+
+/// Strips the classes marked by [`mark_paragraph_classes`] out of a rendered
+/// line, returning the rest of the line and the first class.
+pub fn take_paragraph_class(line: &str) -> (String, Option<String>) {
+    if !line.contains(CLASS_START) {
+        return (line.to_owned(), None);
+    }
+    let mut text = String::with_capacity(line.len());
+    let mut class = None;
+    let mut rest = line;
+    while let Some(start) = rest.find(CLASS_START) {
+        text.push_str(&rest[..start]);
+        let after_start = &rest[start + CLASS_START.len_utf8()..];
+        let Some(end) = after_start.find(CLASS_END) else {
+            rest = after_start;
+            break;
+        };
+        class.get_or_insert_with(|| after_start[..end].to_owned());
+        rest = &after_start[end + CLASS_END.len_utf8()..];
+    }
+    text.push_str(rest);
+    (text, class)
+}
+
+/// `line` with `class` marked at its start again, as [`take_paragraph_class`]
+/// found it.
+pub fn restore_paragraph_class(line: String, class: Option<String>) -> String {
+    match class {
+        Some(class) => format!("{CLASS_START}{class}{CLASS_END}{line}"),
+        None => line,
+    }
+}
+
+/// Ends each line broken with `<br>` through rendering, so
+/// [`unwrap_lines`] can tell it from one wrapping broke.
+const LINE_BREAK: char = '\u{E009}';
+
+/// Marks every `<br>` for [`unwrap_lines`].
+pub fn mark_line_breaks(html: &str) -> String {
+    static BR: OnceLock<Regex> = OnceLock::new();
+    let br = BR.get_or_init(|| Regex::new(r"(?i)<br\b[^>]*>").expect("valid regex"));
+    br.replace_all(html, |caps: &Captures| format!("{LINE_BREAK}{}", &caps[0]))
+        .into_owned()
+}
+
+/// Rendered `text` with the lines wrapping broke at `width` joined back up,
+/// and the marks left by [`mark_line_breaks`] taken out. A line is taken as
+/// wrapped when its first word wouldn't have fit on the one before, unless
+/// that one ended in a `<br>`. A word too long for a line is split into
+/// pieces, at a hyphen if it has one, and those are joined back without a
+/// space, though a lone word that just fills a line can't be told from one.
+pub fn unwrap_lines(text: &str, width: usize) -> String {
+    let mut unwrapped = String::with_capacity(text.len());
+    let mut last_line = "";
+    for line in text.lines() {
+        let line = line.trim_end();
+        let first_word_len = line
+            .split_whitespace()
+            .next()
+            .map_or(0, |it| it.chars().count());
+        let last_len = last_line.chars().count();
+        let wrapped = last_len > 0 && first_word_len > 0 && last_len + 1 + first_word_len > width;
+        if wrapped && !last_line.ends_with(LINE_BREAK) {
+            let split_word = !last_line.contains(char::is_whitespace)
+                && (last_len >= width || last_line.ends_with('-'));
+            // Drop the newline just written, to join onto the line before
+            unwrapped.pop();
+            if !split_word {
+                unwrapped.push(' ');
+            }
+            unwrapped.push_str(line.trim_start());
+        } else {
+            unwrapped.push_str(line);
+        }
+        unwrapped.push('\n');
+        last_line = line.trim();
+    }
+    unwrapped.replace(LINE_BREAK, "")
+}
+
+/// Title cases the words of some HTML, leaving its tags alone.
+fn title_case_text(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    let mut at_word_start = true;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(c);
+                continue;
+            }
+            _ => {}
+        }
+        if in_tag {
+            out.push(c);
+        } else if c.is_alphabetic() {
+            if at_word_start {
+                out.extend(c.to_uppercase());
+            } else {
+                out.extend(c.to_lowercase());
+            }
+            at_word_start = false;
+        } else {
+            // Apostrophes stay inside the word, so DON'T becomes Don't
+            at_word_start = c != '\'' && c != '’';
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// One find-and-replace over a chapter's raw HTML, as written in `books.toml`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Replacement {
+    pub pattern: String,
+    /// Left out to delete every match.
+    #[serde(default)]
+    pub replacement: String,
+    /// Treat `pattern` as a regex, with `$1`-style groups in `replacement`,
+    /// instead of literal text.
+    #[serde(default)]
+    pub regex: bool,
+}
+
+impl Replacement {
+    pub fn literal(pattern: &str, replacement: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            regex: false,
+        }
+    }
+}
+
+/// Replacements every book gets before its own: italics become `<em>` so the
+/// decorator only has one tag to handle.
+pub fn default_replacements() -> Vec<Replacement> {
+    vec![
+        Replacement::literal("<i>", "<em>"),
+        Replacement::literal("</i>", "</em>"),
+    ]
+}
+
+/// A list of [`Replacement`]s compiled once and applied in order.
+pub(crate) struct Rewriter {
+    rules: Vec<(Regex, Replacement)>,
+}
+
+impl Rewriter {
+    pub(crate) fn new<'a>(
+        replacements: impl IntoIterator<Item = &'a Replacement>,
+    ) -> Result<Self, regex::Error> {
+        let rules = replacements
+            .into_iter()
+            .map(|it| {
+                let pattern = if it.regex {
+                    Regex::new(&it.pattern)?
+                } else {
+                    Regex::new(&regex::escape(&it.pattern))?
+                };
+                Ok((pattern, it.clone()))
+            })
+            .collect::<Result<_, regex::Error>>()?;
+        Ok(Self { rules })
+    }
+
+    pub(crate) fn apply(&self, html: &str) -> String {
+        let mut html = html.to_string();
+        for (pattern, rule) in &self.rules {
+            let replaced = if rule.regex {
+                pattern.replace_all(&html, rule.replacement.as_str())
+            } else {
+                pattern.replace_all(&html, NoExpand(&rule.replacement))
+            };
+            html = replaced.into_owned();
+        }
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::attribute;
+
+    #[test]
+    fn attributes_can_be_single_quoted() {
+        assert_eq!(attribute("<p class='break'>", "class"), Some("break"));
+        assert_eq!(
+            attribute(r#"<img alt='The "map"' src="map.jpg"/>"#, "alt"),
+            Some(r#"The "map""#)
+        );
+    }
+
+    #[test]
+    fn attributes_can_follow_any_whitespace() {
+        assert_eq!(
+            attribute("<p\n  class=\"center\">", "class"),
+            Some("center")
+        );
+        assert_eq!(attribute("<p\tclass=\"center\">", "class"), Some("center"));
+    }
+
+    #[test]
+    fn attribute_names_must_match_whole() {
+        assert_eq!(
+            attribute(r#"<p data-class="x" class="y">"#, "class"),
+            Some("y")
+        );
+        assert_eq!(attribute(r#"<p subclass="x">"#, "class"), None);
+    }
+}
@@ -0,0 +1,1113 @@
+//! Turns Cosmere ePubs into paragraph-sized search records.
+//!
+//! Each record carries the paragraph itself as `searchable_text` and the
+//! paragraph with its neighbors, joined as HTML, as `display_text`.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+    io::{Read, Seek},
+    iter,
+    ops::Range,
+    path::Path,
+    sync::OnceLock,
+};
+
+use clap::ValueEnum;
+use epub::doc::EpubDoc;
+use regex::Regex;
+use tracing::{debug, info, warn};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::{display::DisplayTemplate, html::Rewriter};
+
+mod chapter;
+mod config;
+mod cover;
+mod decorator;
+mod display;
+mod ellipsis;
+mod encoding;
+mod epigraph;
+mod footnotes;
+mod glossary;
+mod html;
+mod manifest;
+mod output;
+mod record_id;
+mod schema;
+mod sentence;
+mod source;
+mod sqlite;
+
+pub use chapter::{
+    chapter_numbers, chapter_title, detect_chapter_range, pretty_chapter, section_type, toc_label,
+    toc_range, SectionType,
+};
+pub use config::{
+    builtin_config, find_book, load_config, Config, IndexableBook, Work, DEFAULT_BOOKS_PATH,
+};
+pub use cover::{extract_cover, CoverInfo};
+pub use decorator::{DecoratorFactory, MyDecorator};
+pub use display::DEFAULT_DISPLAY_TEMPLATE;
+pub use html::{default_replacements, Replacement};
+pub use manifest::{file_sha256, Manifest, ManifestBook, ManifestInput, MANIFEST_FILE_NAME};
+pub use output::{
+    existing_book_titles, record_id, BookToc, ChapterGlossary, ChapterSummary, FieldMapping,
+    OutputFormat, OutputSchema, RecordWriter, TocEntry, DEFAULT_ES_INDEX,
+};
+pub use record_id::RECORD_ID_NAMESPACE;
+pub use schema::{output_schema, schema_violations};
+pub use sentence::{chunk_sentences, split_sentences};
+pub use source::{is_epub, is_zip, EpubInput, EpubReader, EpubSource, LoadedEpub};
+pub use sqlite::SqliteWriter;
+
+/// Knobs for how chapters are turned into records.
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    /// How many paragraphs on each side of the center paragraph are joined
+    /// into `display_text`.
+    pub context_radius: usize,
+    /// Skip records whose `searchable_text` already appeared earlier in the
+    /// same book, ignoring case and spacing.
+    pub dedup: bool,
+    /// Lines that mark a break between scenes, compared after trimming.
+    pub scene_borders: Vec<String>,
+    /// Rendered lines starting with one of these, after trimming, are dropped.
+    pub ignorable_prefixes: Vec<String>,
+    /// Regexes for rendered lines to drop, matched against the trimmed line.
+    pub ignorable_patterns: Vec<String>,
+    /// Log each dropped line at debug level, to check no prose is lost.
+    pub log_dropped_lines: bool,
+    /// How paragraphs are joined into `display_text`, with `{prev}`, `{curr}`,
+    /// and `{next}` standing in for them. See [`DEFAULT_DISPLAY_TEMPLATE`].
+    pub display_template: String,
+    /// Put before the paragraphs of `display_text` when a scene starts within
+    /// them, like `* * *`, since the context never crosses a scene break.
+    pub scene_start_marker: Option<String>,
+    /// Keep links as `<a href>` anchors in `display_text`.
+    pub keep_links: bool,
+    /// Keep small-caps text styled as `<span class="sc">` in `display_text`.
+    pub small_caps: bool,
+    /// Keep `<em>` tags in `searchable_text`, for finding italicized terms.
+    pub keep_emphasis: bool,
+    /// Run over every chapter's HTML before the book's own replacements.
+    pub replacements: Vec<Replacement>,
+    /// How much text each record's `searchable_text` covers.
+    pub granularity: Granularity,
+    /// With paragraph granularity, paragraphs longer than this many words are
+    /// split at sentence boundaries into several records.
+    pub max_words_per_record: Option<usize>,
+    /// Reading speed for [`ChapterSummary::reading_minutes`].
+    pub words_per_minute: usize,
+    /// Chapters with fewer words than this whose title or first line reads
+    /// like `Part Two` are skipped as part title pages. `None` keeps them.
+    pub auto_skip_words: Option<usize>,
+    /// What chapters are rendered to text with, for keeping markup that
+    /// [`MyDecorator`] drops.
+    pub decorator: DecoratorFactory,
+    /// How many columns chapters are rendered in, `usize::MAX` for no
+    /// wrapping. Wrapped lines are joined back up before records are built, so
+    /// this only shows in [`chapter_texts`], whose paragraphs are wrapped to it.
+    pub render_width: usize,
+}
+
+impl ParseOptions {
+    /// These options with the settings `book` sets for itself in place of the
+    /// global ones. Its scene borders are added to the global ones.
+    pub fn for_book(&self, book: &IndexableBook) -> Cow<'_, Self> {
+        if book.scene_borders.is_empty() && book.keep_links.is_none() && book.small_caps.is_none() {
+            return Cow::Borrowed(self);
+        }
+        let mut options = self.clone();
+        options
+            .scene_borders
+            .extend(book.scene_borders.iter().cloned());
+        options.keep_links = book.keep_links.unwrap_or(self.keep_links);
+        options.small_caps = book.small_caps.unwrap_or(self.small_caps);
+        Cow::Owned(options)
+    }
+}
+
+/// How finely paragraphs are split into records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Granularity {
+    /// One record per paragraph
+    #[default]
+    Paragraph,
+    /// One record per sentence, still displayed with its whole paragraph
+    Sentence,
+}
+
+/// How `html2text` renders headings and tables, which every book drops
+/// whatever the config adds.
+pub const DEFAULT_IGNORABLE_PREFIXES: [&str; 5] = ["#", "│", "─┴", "─┬", "───────"];
+
+/// Scene break markers that every book gets, whatever the config adds.
+pub const DEFAULT_SCENE_BORDERS: [&str; 2] = [html::SCENE_BREAK_MARKER, "~"];
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            context_radius: 1,
+            dedup: false,
+            scene_borders: DEFAULT_SCENE_BORDERS.map(String::from).to_vec(),
+            ignorable_prefixes: DEFAULT_IGNORABLE_PREFIXES.map(String::from).to_vec(),
+            ignorable_patterns: Vec::new(),
+            log_dropped_lines: false,
+            display_template: String::from(DEFAULT_DISPLAY_TEMPLATE),
+            scene_start_marker: None,
+            keep_links: false,
+            small_caps: false,
+            keep_emphasis: false,
+            replacements: default_replacements(),
+            granularity: Granularity::default(),
+            max_words_per_record: None,
+            words_per_minute: 250,
+            auto_skip_words: Some(15),
+            decorator: DecoratorFactory::default(),
+            render_width: usize::MAX,
+        }
+    }
+}
+
+/// Everything produced from parsing one book.
+#[derive(Debug)]
+pub struct ParsedBook {
+    pub records: Vec<OutputSchema>,
+    pub stats: BookStats,
+    pub chapters: Vec<ChapterSummary>,
+    pub toc: Vec<TocEntry>,
+    pub glossary: Vec<ChapterGlossary>,
+}
+
+/// Counts gathered while parsing a book, for the end-of-run summary.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BookStats {
+    pub chapters: usize,
+    pub records: usize,
+    /// Total words across every record's `searchable_text`.
+    pub words: usize,
+}
+
+impl std::ops::AddAssign for BookStats {
+    fn add_assign(&mut self, other: Self) {
+        self.chapters += other.chapters;
+        self.records += other.records;
+        self.words += other.words;
+    }
+}
+
+/// Parses every configured chapter of `book` out of `doc`, read from
+/// `source_path`, into records, in chapter order.
+pub fn parse_book<R: Read + Seek>(
+    book: &IndexableBook,
+    doc: EpubDoc<R>,
+    source_path: &Path,
+    options: &ParseOptions,
+) -> Result<ParsedBook, Box<dyn Error>> {
+    let mut records = Vec::new();
+    let parsed = parse_book_with(book, doc, source_path, options, |record| {
+        records.push(record);
+        Ok(())
+    })?;
+    Ok(ParsedBook { records, ..parsed })
+}
+
+/// Like [`parse_book`], but hands each record to `emit` as soon as its chapter
+/// is parsed instead of collecting them, so only the chapter being parsed is
+/// held in memory. The book that's returned has its stats, chapter summaries,
+/// and outline, but no records.
+pub fn parse_book_with<R: Read + Seek>(
+    book: &IndexableBook,
+    mut doc: EpubDoc<R>,
+    source_path: &Path,
+    options: &ParseOptions,
+    mut emit: impl FnMut(OutputSchema) -> Result<(), Box<dyn Error>>,
+) -> Result<ParsedBook, Box<dyn Error>> {
+    info!("Parsing {}", book.title);
+    let mut stats = BookStats::default();
+    let mut chapters = Vec::new();
+    let mut toc = Vec::new();
+    let mut glossary = Vec::new();
+    let mut seen_paragraphs = HashSet::new();
+    let parser =
+        ChapterParser::new(book, source_path, options)?.with_metadata(BookMetadata::read(&doc));
+    for_each_chapter(book, &mut doc, |chapter| {
+        let parsed = parser.parse_chapter(chapter)?;
+        if parsed.skipped {
+            info!(
+                "Skipping chapter {} ({}) of {}, it looks like a part title page",
+                chapter.spine_index, chapter.spine_id, book.title
+            );
+            return Ok(());
+        }
+        stats.chapters += 1;
+        if parsed.records.is_empty() {
+            // Usually an image-only page or a chapter range that's off
+            warn!(
+                "Chapter {} ({}) of {} has no text to index",
+                chapter.spine_index, chapter.spine_id, book.title
+            );
+        }
+        let first_record_index = stats.records;
+        for record in parsed.records {
+            if options.dedup
+                && !seen_paragraphs.insert(normalize_for_dedup(&record.searchable_text))
+            {
+                continue;
+            }
+            stats.records += 1;
+            stats.words += record.searchable_text.split_whitespace().count();
+            emit(record)?;
+        }
+
+        let words_per_minute = options.words_per_minute.max(1);
+        chapters.push(ChapterSummary {
+            book_title: book.title.clone(),
+            chapter_title: chapter.title.to_string(),
+            word_count: parsed.word_count,
+            reading_minutes: (parsed.word_count + words_per_minute - 1) / words_per_minute,
+        });
+        let (part_number, chapter_number) = chapter_numbers(chapter.title);
+        toc.push(TocEntry {
+            chapter_title: chapter.title.to_string(),
+            chapter_number,
+            part_number,
+            section_type: section_type(chapter.title),
+            spine_index: chapter.spine_index,
+            first_record_index: (stats.records > first_record_index).then_some(first_record_index),
+        });
+        glossary.push(ChapterGlossary {
+            book_title: book.title.clone(),
+            chapter_title: chapter.title.to_string(),
+            glossary_candidates: parsed.glossary_candidates,
+        });
+        Ok(())
+    })?;
+
+    Ok(ParsedBook {
+        records: Vec::new(),
+        stats,
+        chapters,
+        toc,
+        glossary,
+    })
+}
+
+/// The clean prose of each of `book`'s chapters as Markdown, for reading
+/// offline or diffing editions rather than searching.
+pub fn chapter_texts<R: Read + Seek>(
+    book: &IndexableBook,
+    doc: &mut EpubDoc<R>,
+    source_path: &Path,
+    options: &ParseOptions,
+) -> Result<Vec<ChapterText>, Box<dyn Error>> {
+    let parser =
+        ChapterParser::new(book, source_path, options)?.with_metadata(BookMetadata::read(doc));
+    let mut texts = Vec::new();
+    for_each_chapter(book, doc, |chapter| {
+        if let Some(text) = parser.chapter_text(chapter)? {
+            texts.push(ChapterText {
+                spine_index: chapter.spine_index,
+                title: chapter.title.to_string(),
+                text,
+            });
+        }
+        Ok(())
+    })?;
+    Ok(texts)
+}
+
+/// One chapter's prose, from [`chapter_texts`].
+#[derive(Debug)]
+pub struct ChapterText {
+    pub spine_index: usize,
+    pub title: String,
+    pub text: String,
+}
+
+/// Reads each of `book`'s chapters out of `doc` in spine order, skipping the
+/// skippable ones, and hands it to `f`.
+fn for_each_chapter<R: Read + Seek>(
+    book: &IndexableBook,
+    doc: &mut EpubDoc<R>,
+    mut f: impl FnMut(&Chapter) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let (first_chapter_index, last_chapter_index) = validated_chapter_range(book, doc)?;
+    for chapter_index in first_chapter_index..=last_chapter_index {
+        if book.skippable_chapters.contains(&chapter_index) {
+            continue;
+        }
+        if !doc.set_current_page(chapter_index) {
+            return Err(
+                format!("chapter index {chapter_index} is past the end of the spine").into(),
+            );
+        }
+        let spine_id = doc.spine[chapter_index].clone();
+        debug!(
+            "Parsing chapter {chapter_index} ({spine_id}) of {}",
+            book.title
+        );
+        let (html, _mime_type) = doc
+            .get_current()
+            .ok_or_else(|| format!("couldn't read chapter {chapter_index}"))?;
+        let title = chapter_title(&book.title, doc, chapter_index, &html);
+        let file_name = doc
+            .get_current_path()
+            .and_then(|it| {
+                it.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_default();
+        f(&Chapter {
+            html: &html,
+            title: &title,
+            spine_id: &spine_id,
+            spine_index: chapter_index,
+            file_name: &file_name,
+        })?;
+    }
+    Ok(())
+}
+
+/// One chapter's HTML, with where it sits in its book.
+pub struct Chapter<'a> {
+    pub html: &'a [u8],
+    /// The title readers see, as from [`chapter_title`].
+    pub title: &'a str,
+    /// Its id in the spine, so headings that just repeat it can be dropped.
+    pub spine_id: &'a str,
+    /// Where it sits in the spine.
+    pub spine_index: usize,
+    /// The name of its file, for telling links to its own footnotes apart.
+    pub file_name: &'a str,
+}
+
+/// One chapter's records, before deduplicating across the book.
+pub struct ParsedChapter {
+    pub records: Vec<OutputSchema>,
+    pub word_count: usize,
+    /// It looked like a part title page, so it has no records.
+    pub skipped: bool,
+    /// The distinct terms it italicizes, for [`ChapterGlossary`].
+    pub glossary_candidates: Vec<String>,
+}
+
+/// Turns the chapters of one book into records, with the book's replacements
+/// and line rules compiled once up front. Parsing a chapter doesn't touch the
+/// ePub, so it works on HTML from anywhere, like a benchmark fixture.
+pub struct ChapterParser<'a> {
+    book: &'a IndexableBook,
+    /// The options with the book's own settings applied.
+    options: Cow<'a, ParseOptions>,
+    metadata: BookMetadata,
+    book_id: String,
+    language: String,
+    source_path: String,
+    rewriter: Rewriter,
+    display_template: DisplayTemplate,
+    ignorable_patterns: Vec<Regex>,
+    /// Lowercased text of lines that are page headers or footers when they
+    /// open or close a chapter: the book's title, aliases, and author.
+    running_heads: Vec<String>,
+}
+
+impl<'a> ChapterParser<'a> {
+    pub fn new(
+        book: &'a IndexableBook,
+        source_path: &Path,
+        options: &'a ParseOptions,
+    ) -> Result<Self, Box<dyn Error>> {
+        let rewriter = Rewriter::new(options.replacements.iter().chain(&book.replacements))?;
+        let ignorable_patterns = options
+            .ignorable_patterns
+            .iter()
+            .map(|it| Regex::new(it))
+            .collect::<Result<Vec<_>, _>>()?;
+        let display_template = DisplayTemplate::parse(&options.display_template)?;
+        Ok(Self {
+            book,
+            options: options.for_book(book),
+            metadata: BookMetadata::default(),
+            book_id: output::title_id(&book.title),
+            language: String::from(DEFAULT_LANGUAGE),
+            source_path: source_path.to_string_lossy().into_owned(),
+            rewriter,
+            display_template,
+            ignorable_patterns,
+            running_heads: iter::once(&book.title)
+                .chain(&book.aliases)
+                .map(|it| it.trim().to_lowercase())
+                .collect(),
+        })
+    }
+
+    /// Copies the ePub's `metadata` onto every record.
+    pub(crate) fn with_metadata(mut self, metadata: BookMetadata) -> Self {
+        if let Some(identifier) = &metadata.identifier {
+            self.book_id = identifier.clone();
+        }
+        if let Some(language) = &metadata.language {
+            self.language = language.clone();
+        }
+        if let Some(author) = &metadata.author {
+            self.running_heads.push(author.trim().to_lowercase());
+        }
+        self.metadata = metadata;
+        self
+    }
+
+    fn is_ignorable(&self, line: &str) -> bool {
+        is_ignorable_line(
+            line,
+            &self.options.ignorable_prefixes,
+            &self.ignorable_patterns,
+        )
+    }
+
+    fn is_running_head(&self, line: &str) -> bool {
+        let text = strip_markup(line, false).trim().to_lowercase();
+        self.running_heads.contains(&text)
+    }
+
+    /// Parses `chapter` into its records, in reading order.
+    pub fn parse_chapter(&self, chapter: &Chapter) -> Result<ParsedChapter, Box<dyn Error>> {
+        let (book, options, metadata) = (self.book, &*self.options, &self.metadata);
+        let title = chapter.title.to_string();
+        let (part_number, chapter_number) = chapter_numbers(&title);
+        let section_type = section_type(&title);
+        let mut records = Vec::new();
+        let mut paragraph_index = 0;
+        let mut word_count = 0;
+        // Where the next paragraph starts in the chapter's prose
+        let mut char_offset = 0;
+        let paragraphs = self.paragraphs(chapter)?;
+        if self.is_part_title_page(chapter, &paragraphs) {
+            return Ok(ParsedChapter {
+                records,
+                word_count,
+                skipped: true,
+                glossary_candidates: Vec::new(),
+            });
+        }
+        let glossary_candidates =
+            glossary::glossary_candidates(paragraphs.iter().map(|it| it.text.as_str()));
+        let mut lines_i_care_about = paragraphs.into_iter().peekable();
+
+        let mut head = if book.has_epigraphs {
+            epigraph::leading_lines(&mut lines_i_care_about, |it: &Paragraph| {
+                it.epigraph_hint.is_some()
+            })
+        } else {
+            Vec::new()
+        };
+        let hints: Vec<_> = head.iter().map(|it| it.epigraph_hint).collect();
+        let heading = title.to_lowercase();
+        let is_title = |i: usize| {
+            let line = strip_markup(&head[i].text, false).trim().to_lowercase();
+            !line.is_empty() && heading.contains(&line)
+        };
+        if let Some(range) = epigraph::epigraph_range(&hints, is_title) {
+            // Whatever comes before the epigraph stays in the chapter's prose
+            let epigraph: Vec<Paragraph> = head.drain(range).collect();
+            let lines: Vec<String> = epigraph
+                .iter()
+                .map(|it| strip_markup(&it.text, false))
+                .collect();
+            let text = lines.join(" ");
+            let (quote, epigraph_source) = epigraph::split_attribution(&lines);
+            let quote = quote.join(" ");
+            word_count += text.split_whitespace().count();
+            let searchable_text = if options.keep_emphasis {
+                epigraph
+                    .iter()
+                    .map(|it| strip_markup(&it.text, true))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            } else {
+                text.clone()
+            };
+            let char_end = char_offset + searchable_text.chars().count();
+            let mut out = OutputSchema {
+                id: String::new(),
+                book_title: book.title.clone(),
+                book_id: self.book_id.clone(),
+                chapter_title: title.clone(),
+                chapter_number,
+                part_number,
+                section_type,
+                search_key: search_key(&text),
+                searchable_text,
+                display_text: self
+                    .display_template
+                    .join(epigraph.iter().map(|it| it.text.as_str())),
+                footnotes: epigraph
+                    .iter()
+                    .flat_map(|it| it.footnotes.clone())
+                    .collect(),
+                style_tag: epigraph.iter().find_map(|it| it.style_tag.clone()),
+                images: epigraph.into_iter().flat_map(|it| it.images).collect(),
+                epigraph: Some(quote),
+                epigraph_source,
+                author: metadata.author.clone(),
+                series: metadata.series.clone(),
+                series_index: metadata.series_index,
+                language: self.language.clone(),
+                paragraph_index,
+                sentence_index: None,
+                chunk_index: None,
+                char_start: char_offset,
+                char_end,
+                source_path: self.source_path.clone(),
+            };
+            out.id = record_id(&out);
+            paragraph_index += 1;
+            char_offset = char_end + 1;
+            records.push(out);
+        }
+
+        let radius = options.context_radius;
+        // Padded with `radius` empty slots at either end so the chapter's
+        // first and last paragraphs get to be the center too, with context on
+        // one side only
+        let mut window: VecDeque<Option<Paragraph>> =
+            iter::repeat_with(|| None).take(radius).collect();
+        let padding = iter::repeat_with(|| None).take(radius);
+        for paragraph in head
+            .into_iter()
+            .chain(lines_i_care_about)
+            .map(Some)
+            .chain(padding)
+        {
+            if let Some(paragraph) = &paragraph {
+                if !is_scene_border(&paragraph.text, &options.scene_borders) {
+                    word_count += strip_markup(&paragraph.text, false)
+                        .split_whitespace()
+                        .count();
+                }
+            }
+            window.push_back(paragraph);
+            if window.len() < 2 * radius + 1 {
+                continue;
+            }
+            let values = window.make_contiguous();
+            let (before, rest) = values.split_at(radius);
+            let (curr, after) = rest
+                .split_first()
+                .expect("a full window always has a center line");
+            let curr = curr.as_ref().expect("the padding never reaches the center");
+
+            if !is_scene_border(&curr.text, &options.scene_borders) {
+                // Context stops at a scene break instead of reaching into the
+                // scene on the other side of it
+                let is_border = |it: &Option<Paragraph>| {
+                    it.as_ref()
+                        .is_some_and(|it| is_scene_border(&it.text, &options.scene_borders))
+                };
+                let scene_start = before.iter().rposition(is_border);
+                let before = before[scene_start.map_or(0, |it| it + 1)..]
+                    .iter()
+                    .flatten();
+                let after = after[..after.iter().position(is_border).unwrap_or(after.len())]
+                    .iter()
+                    .flatten();
+                let is_context = |it: &&Paragraph| !self.is_ignorable(&it.text);
+                let marker = scene_start.and(options.scene_start_marker.as_deref());
+                let paragraph_with_context = self.display_template.render(
+                    marker
+                        .into_iter()
+                        .chain(before.filter(is_context).map(|it| it.text.as_str())),
+                    &curr.text,
+                    after.filter(is_context).map(|it| it.text.as_str()),
+                );
+
+                let plain_text = strip_markup(&curr.text, options.keep_emphasis);
+                // Each is (sentence_index, chunk_index, text)
+                let searchable_texts = match options.granularity {
+                    Granularity::Paragraph => match options.max_words_per_record {
+                        Some(max_words) if plain_text.split_whitespace().count() > max_words => {
+                            sentence::chunk_sentences(&plain_text, max_words)
+                                .into_iter()
+                                .enumerate()
+                                .map(|(i, it)| (None, Some(i), it))
+                                .collect()
+                        }
+                        _ => vec![(None, None, plain_text.clone())],
+                    },
+                    Granularity::Sentence => sentence::split_sentences(&plain_text)
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, it)| (Some(i), None, it))
+                        .collect(),
+                };
+                let ranges = char_ranges(
+                    &plain_text,
+                    searchable_texts.iter().map(|(_, _, it)| it.as_str()),
+                );
+                for ((sentence_index, chunk_index, searchable_text), range) in
+                    searchable_texts.into_iter().zip(ranges)
+                {
+                    let mut out = OutputSchema {
+                        id: String::new(),
+                        book_title: book.title.clone(),
+                        book_id: self.book_id.clone(),
+                        chapter_title: title.clone(),
+                        chapter_number,
+                        part_number,
+                        section_type,
+                        search_key: search_key(&strip_markup(&searchable_text, false)),
+                        searchable_text,
+                        display_text: paragraph_with_context.clone(),
+                        footnotes: curr.footnotes.clone(),
+                        images: curr.images.clone(),
+                        style_tag: curr.style_tag.clone(),
+                        epigraph: None,
+                        epigraph_source: None,
+                        author: metadata.author.clone(),
+                        series: metadata.series.clone(),
+                        series_index: metadata.series_index,
+                        language: self.language.clone(),
+                        paragraph_index,
+                        sentence_index,
+                        chunk_index,
+                        char_start: char_offset + range.start,
+                        char_end: char_offset + range.end,
+                        source_path: self.source_path.clone(),
+                    };
+                    out.id = record_id(&out);
+                    records.push(out);
+                }
+                paragraph_index += 1;
+                char_offset += plain_text.chars().count() + 1;
+            }
+            window.pop_front();
+        }
+
+        Ok(ParsedChapter {
+            records,
+            word_count,
+            skipped: false,
+            glossary_candidates,
+        })
+    }
+
+    /// `chapter` as Markdown: a heading with its title, then its
+    /// paragraphs with scene breaks as `* * *` rules. `None` for a part title
+    /// page, which is skipped the same as in [`parse_chapter`](Self::parse_chapter).
+    pub fn chapter_text(&self, chapter: &Chapter) -> Result<Option<String>, Box<dyn Error>> {
+        let paragraphs = self.paragraphs(chapter)?;
+        if self.is_part_title_page(chapter, &paragraphs) {
+            return Ok(None);
+        }
+        let mut text = format!("# {}\n", chapter.title);
+        for paragraph in paragraphs {
+            text.push('\n');
+            if is_scene_border(&paragraph.text, &self.options.scene_borders) {
+                text.push_str(html::SCENE_BREAK_MARKER);
+            } else {
+                let emphasis = paragraph.text.replace("<em>", "_").replace("</em>", "_");
+                text.push_str(&wrap(
+                    &strip_markup(&emphasis, false),
+                    self.options.render_width,
+                ));
+            }
+            text.push('\n');
+        }
+        Ok(Some(text))
+    }
+
+    /// Whether `chapter` looks like a page dividing the book into parts rather
+    /// than prose: short, and titled or opening like `Part Two`. Chapters the
+    /// book lists in `keep_chapters` never are.
+    fn is_part_title_page(&self, chapter: &Chapter, paragraphs: &[Paragraph]) -> bool {
+        let Some(max_words) = self.options.auto_skip_words else {
+            return false;
+        };
+        if self.book.keep_chapters.contains(&chapter.spine_index) {
+            return false;
+        }
+        let texts: Vec<String> = paragraphs
+            .iter()
+            .map(|it| strip_markup(&it.text, false))
+            .collect();
+        let words: usize = texts.iter().map(|it| it.split_whitespace().count()).sum();
+        words < max_words
+            && iter::once(chapter.title)
+                .chain(texts.first().map(String::as_str))
+                .any(|it| section_type(it) == SectionType::Part)
+    }
+
+    /// `chapter` rendered and cleaned up into paragraphs, before any are
+    /// picked out as records.
+    fn paragraphs(&self, chapter: &Chapter) -> Result<Vec<Paragraph>, Box<dyn Error>> {
+        let (book, options) = (self.book, &*self.options);
+        let spine_id = chapter.spine_id;
+        let this_page = encoding::decode_chapter(chapter.html, spine_id);
+        let (this_page, footnotes) = footnotes::extract_footnotes(&this_page, chapter.file_name);
+        let mut this_page = html::mark_images(&html::mark_scene_break_elements(&this_page));
+        if book.has_epigraphs {
+            this_page = epigraph::mark_epigraph_paragraphs(&this_page);
+        }
+        // After the replacements, which may match the original small-caps spans,
+        // drop caps, and paragraph tags
+        let mut this_page_replaced = html::mark_paragraph_classes(&html::mark_small_caps(
+            &html::join_drop_caps(&self.rewriter.apply(&this_page)),
+            options.small_caps,
+        ));
+        let wrapped = options.render_width != usize::MAX;
+        if wrapped {
+            this_page_replaced = html::mark_line_breaks(&this_page_replaced);
+        }
+        let mut page_content = options
+            .decorator
+            .render(this_page_replaced.as_bytes(), options);
+        if wrapped {
+            page_content = html::unwrap_lines(&page_content, options.render_width);
+        }
+        // The class is set aside so the cleanups only see the text
+        let lines_i_care_about = page_content
+            .lines()
+            .map(html::take_paragraph_class)
+            .filter(|(it, _)| {
+                let ignorable = self.is_ignorable(it);
+                if ignorable && options.log_dropped_lines && !it.trim().is_empty() {
+                    debug!("Dropping line from {spine_id}: {it}");
+                }
+                !ignorable
+            })
+            .filter(|(it, _)| !spine_id.ends_with(it.as_str()))
+            .map(|(it, class)| {
+                let it = ellipsis::normalize_ellipses(&it.replace("**", ""));
+                // Decomposed accents wouldn't match what people type into search
+                let it: String = it.nfc().collect();
+                let it = html::restore_small_caps(&collapse_whitespace(&it));
+                html::restore_paragraph_class(it, class)
+            });
+        // The chapter's own heading is dropped the same way, since the first
+        // line would otherwise become a record of its own
+        let heading = chapter.title.to_lowercase();
+        let lines_i_care_about = drop_running_heads(lines_i_care_about, |it| {
+            self.is_running_head(it) || strip_markup(it, false).to_lowercase() == heading
+        });
+        Ok(join_hyphenated_lines(lines_i_care_about)
+            .map(|it| Paragraph::new(&it, &footnotes))
+            .collect())
+    }
+}
+
+/// The language assumed for ePubs whose metadata doesn't give one.
+pub(crate) const DEFAULT_LANGUAGE: &str = "en";
+
+/// The parts of an ePub's metadata copied onto every record.
+#[derive(Default)]
+pub(crate) struct BookMetadata {
+    pub(crate) identifier: Option<String>,
+    pub(crate) author: Option<String>,
+    pub(crate) series: Option<String>,
+    pub(crate) series_index: Option<f32>,
+    pub(crate) language: Option<String>,
+}
+
+impl BookMetadata {
+    pub(crate) fn read<R: Read + Seek>(doc: &EpubDoc<R>) -> Self {
+        // Calibre's own tags first, then the EPUB 3 collection properties
+        let series = doc
+            .mdata("calibre:series")
+            .or_else(|| doc.mdata("belongs-to-collection"));
+        let series_index = doc
+            .mdata("calibre:series_index")
+            .or_else(|| doc.mdata("group-position"))
+            .and_then(|it| it.trim().parse().ok());
+        Self {
+            // The identifier the package itself points to, when there are several
+            identifier: doc
+                .unique_identifier
+                .clone()
+                .or_else(|| doc.mdata("identifier")),
+            author: doc.mdata("creator"),
+            series,
+            series_index,
+            language: doc.mdata("language"),
+        }
+    }
+}
+
+/// A rendered line of a chapter, with the footnotes it references and the
+/// illustrations in it.
+struct Paragraph {
+    text: String,
+    footnotes: Vec<String>,
+    images: Vec<String>,
+    /// Why it looks like part of an epigraph, if it does; only meaningful for
+    /// books that have them.
+    epigraph_hint: Option<epigraph::EpigraphHint>,
+    /// The CSS class of the `<p>` it came from.
+    style_tag: Option<String>,
+}
+
+impl Paragraph {
+    fn new(line: &str, chapter_footnotes: &HashMap<String, String>) -> Self {
+        let (line, style_tag) = html::take_paragraph_class(line);
+        let (line, epigraph_hint) = epigraph::take_epigraph_mark(&line);
+        let (line, ids) = footnotes::take_footnote_refs(&line);
+        let (text, images) = html::take_images(&line);
+        let footnotes = ids
+            .iter()
+            .filter_map(|id| chapter_footnotes.get(id).cloned())
+            .collect();
+        Self {
+            text,
+            footnotes,
+            images,
+            epigraph_hint,
+            style_tag,
+        }
+    }
+}
+
+/// The configured chapter range for `book`, with any missing end filled in
+/// from the table of contents for a work of an omnibus, or else the spine.
+pub fn chapter_range<R: Read + Seek>(book: &IndexableBook, doc: &mut EpubDoc<R>) -> (usize, usize) {
+    match (book.first_chapter_index, book.last_chapter_index) {
+        (Some(first), Some(last)) => (first, last),
+        (first, last) => {
+            let (detected_first, detected_last) = book
+                .toc_entry
+                .as_deref()
+                .and_then(|label| toc_range(doc, label))
+                .unwrap_or_else(|| detect_chapter_range(doc));
+            (
+                first.unwrap_or(detected_first),
+                last.unwrap_or(detected_last),
+            )
+        }
+    }
+}
+
+/// The plain text of a rendered line, without the emphasis, link, or
+/// small-caps tags that `display_text` keeps. With `keep_emphasis`, the `<em>`
+/// tags stay.
+fn strip_markup(line: &str, keep_emphasis: bool) -> String {
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    let tag = TAG.get_or_init(|| Regex::new(r"</?(a|span)\b[^>]*>").expect("valid regex"));
+    let (line, _class) = html::take_paragraph_class(line);
+    let text = tag.replace_all(&line, "");
+    if keep_emphasis {
+        collapse_whitespace(&text)
+    } else {
+        collapse_whitespace(&text.replace("<em>", "").replace("</em>", ""))
+    }
+}
+
+/// `text` trimmed, with each run of whitespace inside it made a single space,
+/// so phrase searches don't miss over a doubled space. A lone non-breaking
+/// space is left as it is.
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() && chars.peek().is_some_and(|it| it.is_whitespace()) {
+            while chars.next_if(|it| it.is_whitespace()).is_some() {}
+            out.push(' ');
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Rejoins words split across two lines with a hyphen, as in poetry and other
+/// preformatted blocks: `sil-` followed by `ver lines` becomes `silver lines`.
+///
+/// Only a hyphen between two lowercase letters counts, so em and en dashes
+/// and hyphens before names or numbers are left alone.
+fn join_hyphenated_lines(lines: impl Iterator<Item = String>) -> impl Iterator<Item = String> {
+    let mut lines = lines.peekable();
+    iter::from_fn(move || {
+        let mut line = lines.next()?;
+        while let Some(next) = lines.next_if(|next| is_split_word(&line, next)) {
+            line.pop();
+            line.push_str(next.trim_start());
+        }
+        Some(line)
+    })
+}
+
+/// `text` broken into lines of at most `width` characters at the spaces
+/// between words. Words longer than that get a line to themselves.
+fn wrap(text: &str, width: usize) -> String {
+    let mut wrapped = String::with_capacity(text.len());
+    let mut line_len = 0;
+    for word in text.split(' ').filter(|it| !it.is_empty()) {
+        let word_len = word.chars().count();
+        if line_len > 0 && line_len + 1 + word_len > width {
+            wrapped.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            wrapped.push(' ');
+            line_len += 1;
+        }
+        wrapped.push_str(word);
+        line_len += word_len;
+    }
+    wrapped
+}
+
+/// Drops running headers and footers: lines that `is_running_head` matches at
+/// the very start or end of a chapter. The same lines in between are kept,
+/// since the chapter may well mention the book by name.
+fn drop_running_heads(
+    lines: impl Iterator<Item = String>,
+    is_running_head: impl Fn(&str) -> bool + Copy,
+) -> impl Iterator<Item = String> {
+    let mut lines = lines.skip_while(move |it| is_running_head(it)).peekable();
+    let mut pending = VecDeque::new();
+    iter::from_fn(move || loop {
+        if let Some(line) = pending.pop_front() {
+            return Some(line);
+        }
+        let line = lines.next()?;
+        if !is_running_head(&line) {
+            return Some(line);
+        }
+        pending.push_back(line);
+        while let Some(next) = lines.next_if(|it| is_running_head(it)) {
+            pending.push_back(next);
+        }
+        // Nothing but running heads left means they're a footer
+        lines.peek()?;
+    })
+}
+
+fn is_split_word(line: &str, next: &str) -> bool {
+    let mut end = line.chars().rev();
+    end.next() == Some('-')
+        && end.next().is_some_and(char::is_lowercase)
+        && next
+            .trim_start()
+            .chars()
+            .next()
+            .is_some_and(char::is_lowercase)
+}
+
+/// Lowercases and collapses whitespace so trivially different copies of a
+/// paragraph compare equal.
+fn normalize_for_dedup(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// `text` folded for matching what people type: lowercase, with accents
+/// dropped and curly quotes, dashes, and ellipses swapped for ASCII ones.
+fn search_key(text: &str) -> String {
+    let mut key = String::with_capacity(text.len());
+    for c in text.nfd().filter(|it| !is_combining_mark(*it)) {
+        match c {
+            '‘' | '’' | '‚' | '′' => key.push('\''),
+            '“' | '”' | '„' | '″' => key.push('"'),
+            '‐' | '‑' | '‒' | '–' | '—' | '―' => key.push('-'),
+            '…' => key.push_str("..."),
+            '\u{a0}' => key.push(' '),
+            _ => key.extend(c.to_lowercase()),
+        }
+    }
+    key
+}
+
+/// Where each of `pieces`, pulled out of `text` in order, sits in it, in
+/// characters. The spaces between words are allowed to differ, for chunks
+/// whose sentences were joined back together with single spaces.
+fn char_ranges<'a>(text: &str, pieces: impl IntoIterator<Item = &'a str>) -> Vec<Range<usize>> {
+    let text: Vec<char> = text.chars().collect();
+    let skip_whitespace = |at: &mut usize| {
+        while text.get(*at).is_some_and(|it| it.is_whitespace()) {
+            *at += 1;
+        }
+    };
+    let mut at = 0;
+    pieces
+        .into_iter()
+        .map(|piece| {
+            skip_whitespace(&mut at);
+            let start = at;
+            for c in piece.chars().filter(|it| !it.is_whitespace()) {
+                skip_whitespace(&mut at);
+                if text.get(at) != Some(&c) {
+                    break;
+                }
+                at += 1;
+            }
+            start..at
+        })
+        .collect()
+}
+
+/// The chapter range for `book`, after checking it and the skippable chapters
+/// actually fit this ePub's spine. Every problem is reported at once.
+pub fn validated_chapter_range<R: Read + Seek>(
+    book: &IndexableBook,
+    doc: &mut EpubDoc<R>,
+) -> Result<(usize, usize), Box<dyn Error>> {
+    let (first, last) = chapter_range(book, doc);
+    let spine_len = doc.spine.len();
+    let mut problems = Vec::new();
+    if let Some(label) = &book.toc_entry {
+        let range_given = book.first_chapter_index.is_some() && book.last_chapter_index.is_some();
+        if !range_given && toc_range(doc, label).is_none() {
+            // Guessing from the spine would take in the whole omnibus
+            problems.push(format!(
+                "no table of contents entry is labelled {label:?} to find the chapter range under"
+            ));
+        }
+    }
+    if first > last {
+        problems.push(format!(
+            "first_chapter_index {first} is after last_chapter_index {last}"
+        ));
+    }
+    for (name, index) in [("first_chapter_index", first), ("last_chapter_index", last)] {
+        if index >= spine_len {
+            problems.push(format!(
+                "{name} {index} is past the end of the {spine_len}-entry spine"
+            ));
+        }
+    }
+    for skippable in &book.skippable_chapters {
+        if !(first..=last).contains(skippable) {
+            problems.push(format!(
+                "skippable chapter {skippable} is outside {first}..={last}"
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok((first, last))
+    } else {
+        Err(format!(
+            "chapter config doesn't fit this ePub: {}",
+            problems.join("; ")
+        )
+        .into())
+    }
+}
+
+/// Whether a rendered line is layout noise (headings, table borders) rather
+/// than prose: blank, starting with one of `prefixes`, or matching one of
+/// `patterns`.
+pub fn is_ignorable_line(line: &str, prefixes: &[String], patterns: &[Regex]) -> bool {
+    let trimmed = line.trim();
+
+    trimmed.is_empty()
+        || prefixes.iter().any(|it| trimmed.starts_with(it.as_str()))
+        || patterns.iter().any(|it| it.is_match(trimmed))
+}
+
+/// Whether a rendered line is one of the `borders` that mark a break between scenes.
+pub fn is_scene_border(line: &str, borders: &[String]) -> bool {
+    let trimmed = line.trim();
+    borders.iter().any(|border| border == trimmed)
+}
@@ -1,370 +1,1179 @@
 use std::{
+    borrow::Cow,
+    collections::HashSet,
+    env,
     error::Error,
-    fs::{self, File},
-    io::{BufReader, Write},
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, IsTerminal, Seek, Write},
     path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::SystemTime,
 };
 
+use clap::Parser;
+use cosmere_epub_parser::{
+    builtin_config, chapter_texts, existing_book_titles, extract_cover, find_book, is_epub, is_zip,
+    load_config, output_schema, parse_book_with, toc_label, validated_chapter_range, BookStats,
+    BookToc, EpubInput, EpubReader, EpubSource, FieldMapping, Granularity, IndexableBook,
+    LoadedEpub, Manifest, ManifestBook, ManifestInput, MyDecorator, OutputFormat, OutputSchema,
+    ParseOptions, ParsedBook, RecordWriter, SqliteWriter, DEFAULT_BOOKS_PATH, DEFAULT_ES_INDEX,
+    MANIFEST_FILE_NAME,
+};
 use epub::doc::EpubDoc;
-use html2text::{from_read_with_decorator, render::text_renderer::TextDecorator};
-use serde::Serialize;
-
-#[derive(Debug)]
-struct IndexableBook {
-    title: String,
-    first_chapter_index: usize,
-    last_chapter_index: usize,
-    skippable_chapters: Vec<usize>,
-}
+use flate2::{write::GzEncoder, Compression};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use tracing::{debug, error, info, warn, Level};
+
+/// Passed as `--output` to write records to stdout instead of a file.
+const STDOUT_PATH: &str = "-";
+
+/// Lists the covers written by `--covers`, inside that directory.
+const COVERS_INDEX: &str = "covers.json";
+
+/// Overrides where the book config is read from.
+const BOOKS_PATH_ENV_VAR: &str = "COSMERE_BOOKS";
+
+/// Exit codes for runs that didn't fail outright but didn't do their job
+/// either. Errors that stop the run early exit with 1.
+const EXIT_NO_EPUBS: u8 = 3;
+const EXIT_NO_MATCHES: u8 = 4;
+const EXIT_PARSE_FAILED: u8 = 5;
+
+/// Turns Cosmere ePubs into search records that the webserver can read at runtime
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// ePub files, directories to scan, glob patterns like `books/**/*.epub`,
+    /// zip archives or ePubs inside one like `series.zip/mistborn.epub`, or
+    /// `http(s)://` URLs
+    #[arg(default_value = ".")]
+    inputs: Vec<String>,
+
+    /// Where to write the parsed records, or `-` for standard output
+    #[arg(short, long, default_value = "output.json")]
+    output: PathBuf,
+
+    /// Layout of the output file
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+
+    /// Elasticsearch index to target with `--format es-bulk`
+    #[arg(long, default_value = DEFAULT_ES_INDEX)]
+    es_index: String,
+
+    /// Write each book's records to its own file in this directory, named
+    /// after the book, instead of to `--output`
+    #[arg(long, conflicts_with_all = ["incremental", "append"])]
+    split_by_book: Option<PathBuf>,
+
+    /// Compress the output with gzip, adding `.gz` to the file names
+    #[arg(long, conflicts_with = "incremental")]
+    gzip: bool,
+
+    /// Also write the records into a SQLite database at this path
+    #[arg(long)]
+    sqlite: Option<PathBuf>,
+
+    /// How many paragraphs on each side of a match to include in `display_text`
+    #[arg(long, default_value_t = ParseOptions::default().context_radius)]
+    context_radius: usize,
+
+    /// Whether each record covers a whole paragraph or a single sentence
+    #[arg(long, value_enum, default_value_t)]
+    granularity: Granularity,
+
+    /// Split paragraphs longer than this many words into several records, at
+    /// sentence boundaries
+    #[arg(long)]
+    max_words_per_record: Option<usize>,
+
+    /// Skip chapters shorter than this many words whose title or first line
+    /// reads like `Part Two`, as part title pages
+    #[arg(long, default_value_t = 15, value_name = "WORDS")]
+    auto_skip_words: usize,
+
+    /// Don't skip anything as a part title page, only `skippable_chapters`
+    #[arg(long)]
+    no_auto_skip: bool,
+
+    /// Drop paragraphs that repeat earlier in the same book
+    #[arg(long)]
+    dedup: bool,
+
+    /// Start `display_text` with this line when the scene begins within it,
+    /// like `"* * *"`
+    #[arg(long, value_name = "TEXT")]
+    scene_start_marker: Option<String>,
+
+    /// Keep links as `<a href>` anchors in `display_text`
+    #[arg(long)]
+    keep_links: bool,
+
+    /// Only parse books that aren't in the output yet, adding them to the end
+    #[arg(long)]
+    incremental: bool,
+
+    /// Add the records to the end of the output instead of replacing it, even
+    /// for books it already has
+    #[arg(long)]
+    append: bool,
+
+    /// Reparse everything even with `--incremental`
+    #[arg(long)]
+    force: bool,
+
+    /// Read the books to index from this file instead of `books.toml`, for
+    /// keeping more than one profile, like one per series
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Only parse books whose title contains one of these, ignoring case;
+    /// repeat or separate with commas for more than one
+    #[arg(long, value_delimiter = ',')]
+    books: Vec<String>,
+
+    /// Also write each chapter's word count and reading time, as NDJSON, to this path
+    #[arg(long)]
+    chapter_stats: Option<PathBuf>,
+
+    /// Also write each chapter's distinct italicized terms, as NDJSON, to this
+    /// path, as a start on a glossary
+    #[arg(long, value_name = "PATH")]
+    glossary: Option<PathBuf>,
+
+    /// Also write each book's outline to this path as JSON: its chapters in
+    /// order, with their numbers, section types, and first record
+    #[arg(long, value_name = "PATH")]
+    toc: Option<PathBuf>,
+
+    /// Reading speed used for `--chapter-stats`
+    #[arg(long, default_value_t = ParseOptions::default().words_per_minute)]
+    words_per_minute: usize,
+
+    /// Also save each book's cover image into this directory, with a
+    /// `covers.json` listing them alongside each book's metadata
+    #[arg(long)]
+    covers: Option<PathBuf>,
+
+    /// Also write each chapter's prose into this directory as a Markdown file,
+    /// for reading offline or diffing editions
+    #[arg(long, value_name = "DIR")]
+    chapters_dir: Option<PathBuf>,
+
+    /// Wrap the paragraphs written by `--chapters-dir` at this many columns;
+    /// records always get whole paragraphs
+    #[arg(long, value_name = "COLUMNS", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    render_width: Option<usize>,
+
+    /// Keep small-caps text styled as `<span class="sc">` in `display_text`
+    #[arg(long)]
+    small_caps: bool,
+
+    /// Keep `<em>` tags in `searchable_text` instead of stripping them
+    #[arg(long)]
+    keep_emphasis: bool,
+
+    /// Check every record against the output schema before writing it
+    #[arg(long)]
+    validate: bool,
+
+    /// Print the JSON Schema for output records and exit
+    #[arg(long)]
+    print_schema: bool,
+
+    /// Print each spine entry of this ePub with its file, table of contents
+    /// label, and opening text, to help pick chapter indices, and exit
+    #[arg(long, value_name = "EPUB")]
+    list_spine: Option<PathBuf>,
+
+    /// Log every rendered line dropped as layout noise (shown with -vv)
+    #[arg(long)]
+    log_dropped_lines: bool,
+
+    /// Only parse ePubs modified after this time, like `2024-05-01T00:00:00Z`
+    #[arg(long, value_name = "RFC3339", value_parser = humantime::parse_rfc3339_weak)]
+    since: Option<SystemTime>,
+
+    /// Only parse the first N ePubs, in path order, that match a configured
+    /// book, for quick sample runs
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_books: Option<usize>,
+
+    /// Show which ePubs would be parsed as which books, without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// List every ePub that matched no configured book at the end of the run,
+    /// to catch a book that's missing from the config
+    #[arg(long)]
+    report_unmatched: bool,
+
+    /// Don't write the `manifest.json` recording the inputs and chapter
+    /// ranges beside the output
+    #[arg(long)]
+    no_manifest: bool,
 
-#[derive(Debug, Serialize)]
-struct OutputSchema {
-    book_title: String,
-    chapter_title: String,
-    searchable_text: String,
-    display_text: String,
+    /// Stop parsing at the first book that fails and exit without writing
+    /// any records, instead of skipping it and carrying on
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// How many ePubs to parse at once; 0 uses every core
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+
+    /// Don't show a progress bar; it's also left out when stderr isn't a terminal
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Log more detail; repeat for even more (-v info, -vv debug, -vvv trace)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only show warnings and errors: no progress bar, summary, or -v logging
+    #[arg(short, long)]
+    quiet: bool,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let all_books: Vec<IndexableBook> = vec![
-        IndexableBook {
-            title: String::from("The Alloy of Law"),
-            first_chapter_index: 7,
-            last_chapter_index: 32,
-            skippable_chapters: vec![10, 16, 22, 26],
-        },
-        IndexableBook {
-            title: String::from("Shadows of Self"),
-            first_chapter_index: 7,
-            last_chapter_index: 37,
-            skippable_chapters: vec![8, 13, 31],
-        },
-        IndexableBook {
-            title: String::from("The Bands of Mourning"),
-            first_chapter_index: 7,
-            last_chapter_index: 42,
-            skippable_chapters: vec![8, 13, 26],
-        },
-        IndexableBook {
-            title: String::from("Secret History"),
-            first_chapter_index: 5,
-            last_chapter_index: 35,
-            skippable_chapters: vec![7, 12, 16, 21, 25],
-        },
-        IndexableBook {
-            title: String::from("Warbreaker"),
-            first_chapter_index: 5,
-            last_chapter_index: 65,
-            skippable_chapters: vec![],
-        },
-        IndexableBook {
-            title: String::from("The Emperor's Soul"),
-            first_chapter_index: 3,
-            last_chapter_index: 18,
-            skippable_chapters: vec![],
-        },
-        IndexableBook {
-            title: String::from("The Hope of Elantris"),
-            first_chapter_index: 28,
-            last_chapter_index: 28,
-            skippable_chapters: vec![],
-        },
-        IndexableBook {
-            title: String::from("The Way of Kings"),
-            first_chapter_index: 8,
-            last_chapter_index: 104, 
-            skippable_chapters: vec![9, 11, 27, 49, 77, 96],
-        }
-    ];
+fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let args = Args::parse();
+    let progress = if args.no_progress || args.quiet || !io::stderr().is_terminal() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(0).with_style(
+            ProgressStyle::with_template(
+                "{elapsed_precise} [{wide_bar}] {pos}/{len} ePubs, {eta} left",
+            )
+            .expect("valid template"),
+        )
+    };
+    let log_progress = progress.clone();
+    tracing_subscriber::fmt()
+        .with_max_level(log_level(if args.quiet { 0 } else { args.verbose }))
+        .with_writer(move || LogWriter(log_progress.clone()))
+        .init();
 
-    let path = Path::new("output.json");
-    let display = path.display();
+    if args.print_schema {
+        println!("{}", serde_json::to_string_pretty(&output_schema())?);
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(path) = &args.list_spine {
+        list_spine(path)?;
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    // Open a file in write-only mode, returns `io::Result<File>`
-    let file = match File::create(path) {
-        Err(why) => panic!("couldn't create {display}: {why}"),
-        Ok(file) => file,
+    let books_path = args
+        .config
+        .clone()
+        .or_else(|| env::var_os(BOOKS_PATH_ENV_VAR).map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_BOOKS_PATH));
+    // A config asked for with `--config` has to be there
+    let (config, config_source) = if args.config.is_some() || books_path.exists() {
+        let config = load_config(&books_path)
+            .map_err(|why| format!("couldn't read {}: {why}", books_path.display()))?;
+        (config, books_path.display().to_string())
+    } else {
+        info!(
+            "No book config at {}, using the built-in list",
+            books_path.display()
+        );
+        (builtin_config(), String::from("built-in"))
     };
+    let all_books = select_books(config.books, &args.books);
 
-    let epub_files: Vec<PathBuf> = fs::read_dir(".")?
-        .flatten()
-        .filter(|it| it.file_type().unwrap().is_file())
-        .filter(|it| it.file_name().to_str().unwrap().ends_with("epub"))
-        .map(|it| it.path().canonicalize().unwrap())
-        .collect();
+    let mut options = ParseOptions {
+        context_radius: args.context_radius,
+        dedup: args.dedup,
+        scene_start_marker: args.scene_start_marker,
+        keep_links: args.keep_links,
+        small_caps: args.small_caps,
+        keep_emphasis: args.keep_emphasis,
+        log_dropped_lines: args.log_dropped_lines,
+        words_per_minute: args.words_per_minute,
+        granularity: args.granularity,
+        max_words_per_record: args.max_words_per_record,
+        auto_skip_words: (!args.no_auto_skip).then_some(args.auto_skip_words),
+        render_width: args.render_width.unwrap_or(usize::MAX),
+        ..ParseOptions::default()
+    };
+    options.scene_borders.extend(config.scene_borders);
+    options.replacements.extend(config.replacements);
+    options.ignorable_prefixes.extend(config.ignorable_prefixes);
+    options.ignorable_patterns = config.ignorable_patterns;
+    if let Some(template) = config.display_template {
+        options.display_template = template;
+    }
+    let fields = config.fields;
+    fields.check()?;
 
-    for path in epub_files {
-        let doc = EpubDoc::new(path);
-        let epub_title = doc
-            .as_ref()
-            .unwrap()
-            .mdata("title")
-            .expect("All ePubs must have a title");
-        println!("Found epub titled: {epub_title}");
-        if let Some(book) = all_books.iter().find(|it| {
-            epub_title
-                .to_lowercase()
-                .contains(it.title.to_lowercase().as_str())
-                || (epub_title.contains("Arcanum Unbounded")
-                    && is_in_arcanum_unbounded(&it.title))
-        }) {
-            parse_and_write_book(book, doc.unwrap(), &file);
+    let mut epub_files = find_epubs(&args.inputs)?;
+    if epub_files.is_empty() {
+        error!("No ePubs found in {}", args.inputs.join(", "));
+        return Ok(ExitCode::from(EXIT_NO_EPUBS));
+    }
+    if let Some(since) = args.since {
+        epub_files.retain(|it| modified_since(it, since));
+        if epub_files.is_empty() {
+            // Nothing new isn't a failure, but the output shouldn't be touched
+            info!(
+                "No ePubs modified since {}",
+                humantime::format_rfc3339(since)
+            );
+            return Ok(ExitCode::SUCCESS);
         }
     }
 
-    Ok(())
-}
+    // The ePub and title of every ePub that matched no book
+    let mut unmatched = Vec::new();
+    // Each ePub is only read in, or downloaded, the first time it's needed
+    let mut epub_files: Vec<EpubInput> = epub_files.into_iter().map(EpubInput::new).collect();
+    if let Some(max_books) = args.max_books {
+        epub_files = first_matches(epub_files, &all_books, max_books, &mut unmatched);
+    }
 
-fn parse_and_write_book(
-    book: &IndexableBook,
-    mut doc: EpubDoc<BufReader<File>>,
-    mut outfile: &File,
-) {
-    println!("Parsing {}", book.title);
-    for chapter_index in book.first_chapter_index..=book.last_chapter_index {
-        if book.skippable_chapters.contains(&chapter_index) {
-            continue;
+    if args.dry_run {
+        print_plan(&epub_files, &all_books, &mut unmatched);
+        if args.report_unmatched {
+            print_unmatched(unmatched);
         }
-        doc.set_current_page(chapter_index);
-        let chapter_title = doc.spine[chapter_index].clone();
-        let (this_page_raw, _mime_type) = doc.get_current().unwrap();
-        let this_page = String::from_utf8(this_page_raw).unwrap();
-        let this_page_replaced = this_page
-            .replace("<i>", "<em>")
-            .replace("</i>", "</em>")
-            .replace("<img", "<img alt=\"795f88d2-e400-42f0-bb88-d84cf308de1b\"")
-            .replace("<p class=\"Part-Title-pt\"><a href=\"contents.xhtml#c_pt3\"><span class=\"ePub-SC\">THE</span><br/>HOPE<br/><span class=\"ePub-SC\">OF</span><br/>ELANTRIS</a></p>", "")
-            .replace("<p class=\"Design-Note-dn\"><span class=\"R1\">This story takes place after and contains major spoilers for</span> <span class=\"ePub-I\">Elantris.</span></p>", "");
-        let page_content = from_read_with_decorator(
-            this_page_replaced.as_bytes(),
-            usize::MAX,
-            MyDecorator::new(),
-        );
-        // println!("{}", page_content);
-        let lines_i_care_about: Vec<String> = page_content
-            .lines()
-            .filter(|it| !is_ignorable_line(it))
-            .filter(|it| !chapter_title.ends_with(it))
-            .map(|it| it.replace("**", ""))
-            .map(|it| it.replace(". . .", "…"))
-            .map(|it| it.replace(" …", "…"))
-            .collect();
-        for values in lines_i_care_about.windows(3) {
-            let prev = values
-                .first()
-                .expect(".windows() returns exactly 3 elements");
-            let curr = values
-                .get(1)
-                .expect(".windows() returns exactly 3 elements");
-            let next = values
-                .get(2)
-                .expect(".windows() returns exactly 3 elements");
-
-            if is_scene_border(curr) {
-                continue;
-            }
+        return Ok(ExitCode::SUCCESS);
+    }
+    progress.set_length(epub_files.len() as u64);
+    // Rayon takes 0 to mean one thread per core
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads)
+        .build_global()?;
 
-            // handle scene divisions
-            let prev_line = if is_scene_border(prev) || is_ignorable_line(prev) {
-                String::new()
-            } else {
-                format!("{prev}</p><p>")
-            };
+    let to_stdout = args.output == Path::new(STDOUT_PATH);
+    let path = if args.gzip && !to_stdout {
+        gz_path(&args.output)
+    } else {
+        args.output.clone()
+    };
+    let path = path.as_path();
+    let incremental = args.incremental && !args.force && !to_stdout && path.exists();
+    let append = (incremental || args.append) && !to_stdout && path.exists();
+    let already_written = if incremental {
+        existing_book_titles(path, args.format, &fields)?
+    } else {
+        HashSet::new()
+    };
 
-            let next_line = if is_scene_border(next) || is_ignorable_line(next) {
-                String::new()
-            } else {
-                format!("</p><p>{next}")
+    for dir in [&args.covers, &args.chapters_dir].into_iter().flatten() {
+        fs::create_dir_all(dir)?;
+    }
+    let covers = Mutex::new(Vec::new());
+    let write_manifest = !args.no_manifest && !to_stdout;
+    let manifest_inputs = Mutex::new(Vec::new());
+    let manifest_books = Mutex::new(Vec::new());
+    let matched = AtomicUsize::new(0);
+    let unmatched = Mutex::new(unmatched);
+    let failed = AtomicBool::new(false);
+    // Books are parsed in parallel but always written in title order so the
+    // output doesn't depend on thread scheduling
+    let mut parsed_books: Vec<_> = epub_files
+        .into_par_iter()
+        .flat_map_iter(|input| {
+            let source = &input.source;
+            let _done = ProgressTick(&progress);
+            if args.fail_fast && failed.load(Ordering::Relaxed) {
+                return Vec::new();
+            }
+            let epub_path = source.name();
+            let epub = match input.load() {
+                Ok(epub) => epub,
+                Err(why) => {
+                    warn!("Skipping {source}: {why}");
+                    return Vec::new();
+                }
             };
-
-            let paragraph_with_context = format!("{prev_line}{curr}{next_line}");
-
-            let out = OutputSchema {
-                book_title: book.title.clone(),
-                chapter_title: pretty_chapter(&book.title, &chapter_title),
-                searchable_text: curr.clone().replace("<em>", "").replace("</em>", ""),
-                display_text: paragraph_with_context,
+            if write_manifest {
+                match epub.sha256() {
+                    Ok(sha256) => manifest_inputs.lock().unwrap().push(ManifestInput {
+                        path: epub_path.display().to_string(),
+                        sha256,
+                    }),
+                    Err(why) => warn!("Couldn't hash {source}: {why}"),
+                }
+            }
+            let Some((doc, epub_title)) = open_epub(epub, &epub_path) else {
+                return Vec::new();
             };
-            let mut json = serde_json::to_string(&out).unwrap();
-            json.push('\n');
-            outfile.write_all(json.as_bytes()).unwrap();
-        }
+            info!("Found epub titled: {epub_title}");
+            let epub_identifier = doc.unique_identifier.clone();
+            let Some(book) = find_book(&all_books, &epub_title, epub_identifier.as_deref()) else {
+                warn!("No configured book matches {epub_title}");
+                unmatched
+                    .lock()
+                    .unwrap()
+                    .push((source.to_string(), epub_title));
+                return Vec::new();
+            };
+            matched.fetch_add(1, Ordering::Relaxed);
+            let mut doc = Some(doc);
+            book.works()
+                .into_iter()
+                .filter_map(|book| {
+                    // Each work of an omnibus reads the ePub from the start
+                    let mut doc = match doc.take() {
+                        Some(doc) => doc,
+                        None => open_epub(epub, &epub_path)?.0,
+                    };
+                    if let Some(dir) = &args.covers {
+                        match extract_cover(&book, &mut doc, dir) {
+                            Ok(cover) => covers.lock().unwrap().push(cover),
+                            Err(why) => {
+                                warn!("Couldn't extract the cover of {}: {why}", book.title)
+                            }
+                        }
+                    }
+                    if already_written.contains(&book.title) {
+                        info!(
+                            "Skipping {}, it's already in {}",
+                            book.title,
+                            path.display()
+                        );
+                        return None;
+                    }
+                    if let Some(dir) = &args.chapters_dir {
+                        if let Err(why) =
+                            write_chapter_texts(&book, &mut doc, &epub_path, &options, dir)
+                        {
+                            warn!("Couldn't write the chapters of {}: {why}", book.title);
+                        }
+                    }
+                    // Worked out again by the parser, but cheap next to the parse itself
+                    let range = write_manifest
+                        .then(|| validated_chapter_range(&book, &mut doc).ok())
+                        .flatten();
+                    let parsed =
+                        spool_book(&book, doc, &epub_path, &options).map_err(|why| why.to_string());
+                    if parsed.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    if let (Ok(_), Some((first_chapter_index, last_chapter_index))) =
+                        (&parsed, range)
+                    {
+                        manifest_books.lock().unwrap().push(ManifestBook {
+                            title: book.title.clone(),
+                            path: epub_path.display().to_string(),
+                            first_chapter_index,
+                            last_chapter_index,
+                            skippable_chapters: book.skippable_chapters.clone(),
+                        });
+                    }
+                    Some((book, parsed))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    // Stable, so editions sharing a title stay in path order
+    parsed_books.sort_by(|(a, _), (b, _)| a.title.cmp(&b.title));
+    if let Some(dir) = &args.covers {
+        let mut covers = covers.into_inner().unwrap();
+        covers.sort_by(|a, b| a.book_title.cmp(&b.book_title));
+        let file = BufWriter::new(File::create(dir.join(COVERS_INDEX))?);
+        serde_json::to_writer_pretty(file, &covers)?;
     }
-}
 
-fn is_ignorable_line(line: &str) -> bool {
-    let trimmed = line.trim();
+    if args.fail_fast && failed.into_inner() {
+        for (book, parsed) in &parsed_books {
+            if let Err(why) = parsed {
+                error!("Couldn't parse {}: {why}", book.title);
+            }
+        }
+        progress.finish_and_clear();
+        if args.report_unmatched {
+            print_unmatched(unmatched.into_inner().unwrap());
+        }
+        return Ok(ExitCode::from(EXIT_PARSE_FAILED));
+    }
+    // Opened only now so a failed run leaves any existing output untouched
+    let mut output = if let Some(dir) = &args.split_by_book {
+        fs::create_dir_all(dir)?;
+        Output::PerBook {
+            dir: dir.clone(),
+            format: args.format,
+            es_index: args.es_index.clone(),
+            validate: args.validate,
+            fields: fields.clone(),
+            gzip: args.gzip,
+            current: None,
+        }
+    } else {
+        let writer = if to_stdout {
+            let out = compressed(BufWriter::new(io::stdout().lock()), args.gzip);
+            RecordWriter::new(out, args.format)?
+        } else if append {
+            // A gzip stream can't be extended, but a second one after it
+            // decompresses as if it were
+            let file = OpenOptions::new().append(true).open(path)?;
+            RecordWriter::append(compressed(BufWriter::new(file), args.gzip), args.format)?
+        } else {
+            let file = File::create(path)
+                .map_err(|why| format!("couldn't create {}: {why}", path.display()))?;
+            RecordWriter::new(compressed(BufWriter::new(file), args.gzip), args.format)?
+        };
+        Output::Single(
+            writer
+                .with_es_index(args.es_index.clone())
+                .with_validation(args.validate)
+                .with_fields(fields.clone()),
+        )
+    };
+    let open_sqlite = if append {
+        SqliteWriter::append
+    } else {
+        SqliteWriter::create
+    };
+    let mut sqlite = args.sqlite.as_deref().map(open_sqlite).transpose()?;
+    let mut chapter_stats = args
+        .chapter_stats
+        .as_deref()
+        .map(|it| File::create(it).map(BufWriter::new))
+        .transpose()?;
+    let mut glossary = args
+        .glossary
+        .as_deref()
+        .map(|it| File::create(it).map(BufWriter::new))
+        .transpose()?;
 
-    trimmed.is_empty()
-        || trimmed.starts_with('#')
-        || trimmed.starts_with('│')
-        || trimmed.starts_with("─┴")
-        || trimmed.starts_with("─┬")
-        || trimmed.starts_with("───────")
+    let mut toc = args.toc.as_ref().map(|_| Vec::new());
+    let written = write_books(
+        &mut output,
+        sqlite.as_mut(),
+        chapter_stats.as_mut(),
+        glossary.as_mut(),
+        toc.as_mut(),
+        parsed_books,
+    );
+    // Finish even if writing failed partway so the output stays well-formed
+    output.finish()?;
+    for mut out in [chapter_stats, glossary].into_iter().flatten() {
+        out.flush()?;
+    }
+    if let (Some(path), Some(toc)) = (&args.toc, toc) {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, &toc)?;
+    }
+    progress.finish_and_clear();
+    if args.report_unmatched {
+        print_unmatched(unmatched.into_inner().unwrap());
+    }
+    if write_manifest {
+        let dir = match &args.split_by_book {
+            Some(dir) => dir.as_path(),
+            None => path.parent().unwrap_or(Path::new("")),
+        };
+        let manifest = Manifest::new(
+            config_source,
+            manifest_inputs.into_inner().unwrap(),
+            manifest_books.into_inner().unwrap(),
+        );
+        let file = BufWriter::new(File::create(dir.join(MANIFEST_FILE_NAME))?);
+        serde_json::to_writer_pretty(file, &manifest)?;
+    }
+    let written = written?;
+    if !args.quiet {
+        print_summary(&written);
+    }
+    if matched.into_inner() == 0 {
+        error!("None of the ePubs matched a configured book");
+        return Ok(ExitCode::from(EXIT_NO_MATCHES));
+    }
+    Ok(ExitCode::SUCCESS)
 }
 
-fn is_scene_border(line: &str) -> bool {
-    let borders = vec!["* * *", "~", "795f88d2-e400-42f0-bb88-d84cf308de1b"];
-    borders.contains(&line)
+/// Where records are written: all to one output, or each book's to its own
+/// file in a directory.
+enum Output {
+    Single(RecordWriter<Box<dyn Write>>),
+    PerBook {
+        dir: PathBuf,
+        format: OutputFormat,
+        es_index: String,
+        validate: bool,
+        fields: FieldMapping,
+        gzip: bool,
+        /// The file being written and the book it's for. Editions sharing a
+        /// title are written one after another, so they share a file.
+        current: Option<(String, RecordWriter<Box<dyn Write>>)>,
+    },
 }
 
-#[allow(clippy::case_sensitive_file_extension_comparisons)]
-fn pretty_chapter(book_title: &str, raw_chapter: &str) -> String {
-    let lowercase_chapter = raw_chapter.to_ascii_lowercase();
-    if book_title.eq_ignore_ascii_case("The Hope of Elantris") {
-        String::new()
-    } else if lowercase_chapter == "pre" {
-        String::from("Prelude")
-    } else if lowercase_chapter == "prologue" || lowercase_chapter == "pro" {
-        String::from("Prologue")
-    } else if lowercase_chapter == "epilogue" || lowercase_chapter == "epi" {
-        String::from("Epilogue")
-    } else if lowercase_chapter.starts_with("chapter") || lowercase_chapter.starts_with("c"){
-        let num: String = lowercase_chapter
-            .chars()
-            .into_iter()
-            .filter(char::is_ascii_digit)
-            .collect();
-        format!("Chapter {}", num.trim_start_matches('0'))
-    } else if raw_chapter.starts_with('x') && raw_chapter.ends_with(".html") {
-        handle_secret_history_chapter(raw_chapter)
-    } else {
-        String::from(map_by_hand(raw_chapter))
+impl Output {
+    /// The writer for `title`'s records, starting its file if it's new.
+    fn writer_for(
+        &mut self,
+        title: &str,
+    ) -> Result<&mut RecordWriter<Box<dyn Write>>, Box<dyn Error>> {
+        match self {
+            Self::Single(writer) => Ok(writer),
+            Self::PerBook {
+                dir,
+                format,
+                es_index,
+                validate,
+                fields,
+                gzip,
+                current,
+            } => {
+                if current.as_ref().map_or(true, |(it, _)| it != title) {
+                    if let Some((_, writer)) = current.take() {
+                        writer.finish()?;
+                    }
+                    let mut path = dir.join(format!("{}.{}", slug(title), format.extension()));
+                    if *gzip {
+                        path = gz_path(&path);
+                    }
+                    let file = compressed(BufWriter::new(File::create(path)?), *gzip);
+                    let writer = RecordWriter::new(file, *format)?
+                        .with_es_index(es_index.clone())
+                        .with_validation(*validate)
+                        .with_fields(fields.clone());
+                    *current = Some((title.to_string(), writer));
+                }
+                Ok(&mut current.as_mut().expect("opened above").1)
+            }
+        }
     }
-}
 
-fn handle_secret_history_chapter(raw_chapter: &str) -> String {
-    let part_number = raw_chapter.chars().nth(1).unwrap();
-    let chapter_number = raw_chapter.chars().nth(3).unwrap();
-    format!("Part {part_number}, Chapter {chapter_number}")
+    fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::Single(writer)
+            | Self::PerBook {
+                current: Some((_, writer)),
+                ..
+            } => {
+                writer.finish()?;
+            }
+            Self::PerBook { current: None, .. } => {}
+        }
+        Ok(())
+    }
 }
 
-fn map_by_hand(raw_chapter: &str) -> &str {
-    match raw_chapter {
-        "Prologue.html" => "Prologue",
-        "Day_02.html" => "Day Two",
-        "Day_03.html" => "Day Three",
-        "Day_05.html" => "Day Five",
-        "Day_12.html" => "Day Twelve",
-        "Day_17.html" => "Day Seventeen",
-        "Day_30.html" => "Day Thirty",
-        "Day_42.html" => "Day Forty-Two",
-        "Day_58.html" => "Day Fifty-Eight",
-        "Day_59.html" => "Day Fifty-Nine",
-        "Day_70.html" => "Day Seventy",
-        "Day_76.html" => "Day Seventy-Six",
-        "Day_85.html" => "Day Eighty-Five",
-        "Day_97.html" => "Day Ninety-Seven",
-        "Day_98.html" => "Day Ninety-Eight",
-        "Epilogue.html" => "Epilogue: Day One Hundred and One",
-        "p01a_c01" => "Interlude 1",
-        "p01a_c02" => "Interlude 2",
-        "p01a_c03" => "Interlude 3",
-        "p02a_c04" => "Interlude 4",
-        "p02a_c05" => "Interlude 5",
-        "p02a_c06" => "Interlude 6",
-        "p03a_c07" => "Interlude 7",
-        "p03a_c08" => "Interlude 8",
-        "p03a_c09" => "Interlude 9",
-        "end-note" => "Endnote",
-        _ => raw_chapter,
+/// `out`, gzipped if asked for. The gzip trailer is written when the encoder
+/// is dropped, after [`RecordWriter::finish`] has flushed everything into it.
+fn compressed(out: impl Write + 'static, gzip: bool) -> Box<dyn Write> {
+    if gzip {
+        Box::new(GzEncoder::new(out, Compression::default()))
+    } else {
+        Box::new(out)
     }
 }
 
-fn is_in_arcanum_unbounded(title: &str) -> bool {
-    match title {
-        "The Hope of Elantris"
-        | "The Eleventh Metal"
-        | "Allomancer Jak and the Pits of Eltania"
-        | "White Sand"
-        | "Shadows for Silence in the Forests of Hell"
-        | "Sixth of the Dusk"
-        | "Edgedancer" => true,
-        _ => false,
+/// `path` with `.gz` added, unless it already ends in it.
+fn gz_path(path: &Path) -> PathBuf {
+    if path.extension().is_some_and(|it| it == "gz") {
+        return path.to_path_buf();
     }
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
 }
 
-#[derive(Clone, Debug)]
-struct MyDecorator {}
+/// A book title as a file name: lowercase words joined by hyphens, so
+/// `Mistborn: Secret History` becomes `mistborn-secret-history`.
+fn slug(title: &str) -> String {
+    title
+        .to_lowercase()
+        .replace(['\'', '’'], "")
+        .split(|it: char| !it.is_alphanumeric())
+        .filter(|it| !it.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
 
-impl MyDecorator {
-    pub const fn new() -> Self {
-        Self {}
+/// Writes each of `book`'s chapters into `dir` as Markdown, named like
+/// `warbreaker-007-chapter-3.md` so they sort in reading order.
+fn write_chapter_texts(
+    book: &IndexableBook,
+    doc: &mut EpubDoc<EpubReader>,
+    epub_path: &Path,
+    options: &ParseOptions,
+    dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    for chapter in chapter_texts(book, doc, epub_path, options)? {
+        let file_name = format!(
+            "{}-{:03}-{}.md",
+            slug(&book.title),
+            chapter.spine_index,
+            slug(&chapter.title)
+        );
+        fs::write(dir.join(file_name), chapter.text)?;
     }
+    Ok(())
 }
 
-impl TextDecorator for MyDecorator {
-    type Annotation = ();
+/// Writes log lines to stderr, moving the progress bar out of their way.
+struct LogWriter(ProgressBar);
 
-    fn decorate_code_start(&mut self) -> (String, Self::Annotation) {
-        (String::new(), ())
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.suspend(|| io::stderr().write(buf))
     }
 
-    fn decorate_code_end(&mut self) -> String {
-        String::new()
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
     }
+}
 
-    fn decorate_em_start(&mut self) -> (String, Self::Annotation) {
-        (String::from("<em>"), ())
-    }
+/// Advances the progress bar once an ePub is done with, however that went.
+struct ProgressTick<'a>(&'a ProgressBar);
 
-    fn decorate_em_end(&mut self) -> String {
-        String::from("</em>")
+impl Drop for ProgressTick<'_> {
+    fn drop(&mut self) {
+        self.0.inc(1);
     }
+}
 
-    fn decorate_image(&mut self, _src: &str,_title: &str) -> (String, Self::Annotation) {
-        (String::new(), ())
+/// The configured books whose titles contain any of `filters`, or all of
+/// them when there are no filters. An omnibus none of them match is kept with
+/// just the works they do.
+fn select_books(books: Vec<IndexableBook>, filters: &[String]) -> Vec<IndexableBook> {
+    if filters.is_empty() {
+        return books;
     }
+    let filters: Vec<String> = filters.iter().map(|it| it.trim().to_lowercase()).collect();
+    let matches = |title: &str| {
+        let title = title.to_lowercase();
+        filters.iter().any(|it| title.contains(it.as_str()))
+    };
+    let selected: Vec<IndexableBook> = books
+        .into_iter()
+        .filter_map(|mut book| {
+            // Picking works out of an omnibus keeps just those
+            if !matches(&book.title) {
+                book.works.retain(|it| matches(&it.title));
+                if book.works.is_empty() {
+                    return None;
+                }
+            }
+            Some(book)
+        })
+        .collect();
+    if selected.is_empty() {
+        warn!("No configured book matches --books {}", filters.join(","));
+    }
+    selected
+}
 
-    fn decorate_link_start(&mut self, _url: &str) -> (String, Self::Annotation) {
-        (String::new(), ())
+/// Expands the inputs into the ePubs to read. Files are taken as given, zip
+/// archives for every ePub inside, directories are scanned (not recursively),
+/// and URLs are downloaded later on. Anything else is a path inside a zip
+/// archive, like `series.zip/mistborn.epub`, or else a glob.
+fn find_epubs(inputs: &[String]) -> Result<Vec<EpubSource>, Box<dyn Error>> {
+    let mut epub_files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if let Some(url) = EpubSource::url(input) {
+            epub_files.push(url);
+        } else if path.is_file() && is_zip(path) {
+            epub_files.extend(zip_members(path));
+        } else if path.is_file() {
+            epub_files.push(EpubSource::File(path.to_path_buf()));
+        } else if path.is_dir() {
+            // One unreadable entry shouldn't hide the rest of the directory
+            for entry in fs::read_dir(path)? {
+                match entry.and_then(|it| Ok((it.file_type()?, it.path()))) {
+                    Ok((file_type, path)) if file_type.is_file() && is_epub(&path) => {
+                        epub_files.push(EpubSource::File(path));
+                    }
+                    Ok(_) => {}
+                    Err(why) => warn!("Skipping an entry in {input}: {why}"),
+                }
+            }
+        } else if let Some(member) = EpubSource::archive_member(path) {
+            epub_files.push(member);
+        } else {
+            let mut matches = Vec::new();
+            for entry in glob::glob(input)? {
+                match entry {
+                    Ok(path) if path.is_file() && is_epub(&path) => {
+                        matches.push(EpubSource::File(path));
+                    }
+                    Ok(path) if path.is_file() && is_zip(&path) => {
+                        matches.extend(zip_members(&path));
+                    }
+                    Ok(_) => {}
+                    Err(why) => warn!("Skipping {}: {}", why.path().display(), why.error()),
+                }
+            }
+            if matches.is_empty() {
+                warn!("No ePubs found for {input}");
+            }
+            epub_files.extend(matches);
+        }
     }
 
-    fn decorate_link_end(&mut self) -> String {
-        String::new()
+    // The same book can be reached through more than one input
+    let mut epub_files: Vec<EpubSource> = epub_files
+        .into_iter()
+        .filter_map(|it| {
+            let canonical = match &it {
+                EpubSource::File(path) => path.canonicalize().map(EpubSource::File),
+                EpubSource::ArchiveMember { archive, member } => {
+                    archive
+                        .canonicalize()
+                        .map(|archive| EpubSource::ArchiveMember {
+                            archive,
+                            member: member.clone(),
+                        })
+                }
+                EpubSource::Url(_) => Ok(it.clone()),
+            };
+            match canonical {
+                Ok(source) => Some(source),
+                Err(why) => {
+                    warn!("Skipping {it}: {why}");
+                    None
+                }
+            }
+        })
+        .collect();
+    // Directory order varies between file systems; sorting keeps runs over
+    // the same files byte-for-byte identical
+    epub_files.sort();
+    epub_files.dedup();
+    Ok(epub_files)
+}
+
+/// The ePubs inside the zip archive at `path`, warning and giving up on one
+/// that can't be read.
+fn zip_members(path: &Path) -> Vec<EpubSource> {
+    match EpubSource::archive_members(path) {
+        Ok(members) => {
+            if members.is_empty() {
+                warn!("No ePubs found in {}", path.display());
+            }
+            members
+        }
+        Err(why) => {
+            warn!(
+                "Skipping {}: not a readable zip archive ({why})",
+                path.display()
+            );
+            Vec::new()
+        }
     }
+}
 
-    fn decorate_preformat_first(&mut self) -> Self::Annotation {}
+/// Opens an ePub and reads its title, warning and giving up on files that
+/// aren't valid ePubs. Stripped ePubs without a `dc:title` go by their file
+/// name instead, like `the_way_of_kings.epub` as `the way of kings`.
+fn open_epub(epub: &LoadedEpub, path: &Path) -> Option<(EpubDoc<EpubReader>, String)> {
+    let doc = match epub.open() {
+        Ok(doc) => doc,
+        Err(why) => {
+            warn!("Skipping {}: not a readable ePub ({why})", path.display());
+            return None;
+        }
+    };
+    let epub_title = match doc.mdata("title").filter(|it| !it.trim().is_empty()) {
+        Some(title) => title,
+        None => {
+            let Some(title) = title_from_file_name(path) else {
+                warn!("Skipping {}: it has no title", path.display());
+                return None;
+            };
+            warn!("{} has no title, matching it as {title:?}", path.display());
+            title
+        }
+    };
+    Some((doc, epub_title))
+}
 
-    fn decorate_preformat_cont(&mut self) -> Self::Annotation {}
+/// An ePub's file name without the extension, with `_` and `-` read as spaces.
+fn title_from_file_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let title = stem
+        .split(|it: char| it == '_' || it == '-' || it.is_whitespace())
+        .filter(|it| !it.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!title.is_empty()).then_some(title)
+}
 
-    fn decorate_strikeout_start(&mut self) -> (String, Self::Annotation) {
-        (String::from("<s>"), ())
+/// Whether the file `source` is read from was modified after `since`. URLs
+/// and files whose time can't be read are kept, since skipping them would hide
+/// them for good.
+fn modified_since(source: &EpubSource, since: SystemTime) -> bool {
+    let Some(path) = source.local_file() else {
+        return true;
+    };
+    match fs::metadata(path).and_then(|it| it.modified()) {
+        Ok(modified) if modified > since => true,
+        Ok(_) => {
+            debug!("Skipping {source}, it hasn't changed since then");
+            false
+        }
+        Err(why) => {
+            warn!("Couldn't read when {} was modified: {why}", path.display());
+            true
+        }
     }
+}
 
-    fn decorate_strikeout_end(&mut self) -> String {
-        String::from("</s>")
+/// How much of each spine entry's text `--list-spine` shows.
+const SPINE_PREVIEW_CHARS: usize = 60;
+
+/// Prints every spine entry of the ePub at `path` with its file, TOC label, and
+/// the start of its text.
+fn list_spine(path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut doc = EpubDoc::new(path)?;
+    for index in 0..doc.spine.len() {
+        let id = doc.spine[index].clone();
+        let file = doc
+            .resources
+            .get(&id)
+            .map(|(it, _mime)| it.display().to_string())
+            .unwrap_or_default();
+        let label = toc_label(&doc, index).unwrap_or_default();
+        doc.set_current_page(index);
+        let preview = doc
+            .get_current()
+            .map(|(html, _mime)| text_preview(&html))
+            .unwrap_or_default();
+        println!("{index:>4}  {id}  {file}  {label:?}  {preview}");
     }
+    Ok(())
+}
 
-    fn decorate_strong_start(&mut self) -> (String, Self::Annotation) {
-        (String::new(), ())
+/// The first [`SPINE_PREVIEW_CHARS`] characters of some HTML's rendered text,
+/// all on one line.
+fn text_preview(html: &[u8]) -> String {
+    let text = html2text::from_read_with_decorator(html, usize::MAX, MyDecorator::new())
+        .replace("<em>", "")
+        .replace("</em>", "");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > SPINE_PREVIEW_CHARS {
+        let cut: String = text.chars().take(SPINE_PREVIEW_CHARS).collect();
+        format!("{cut}…")
+    } else {
+        text
     }
+}
 
-    fn decorate_strong_end(&mut self) -> String {
-        String::new()
+/// The first `max_books` of `epub_files` that match one of `all_books`. Each
+/// is opened to read its title, so the rest are never looked at. The ones
+/// passed over for matching nothing are added to `unmatched`.
+fn first_matches(
+    epub_files: Vec<EpubInput>,
+    all_books: &[IndexableBook],
+    max_books: usize,
+    unmatched: &mut Vec<(String, String)>,
+) -> Vec<EpubInput> {
+    let mut matches = Vec::new();
+    for input in epub_files {
+        if matches.len() == max_books {
+            break;
+        }
+        let source = &input.source;
+        let path = source.name();
+        let epub = match input.load() {
+            Ok(epub) => epub,
+            Err(why) => {
+                warn!("Skipping {source}: {why}");
+                continue;
+            }
+        };
+        let Some((doc, epub_title)) = open_epub(epub, &path) else {
+            continue;
+        };
+        if find_book(all_books, &epub_title, doc.unique_identifier.as_deref()).is_some() {
+            matches.push(input);
+        } else {
+            warn!("No configured book matches {epub_title}");
+            unmatched.push((source.to_string(), epub_title));
+        }
     }
+    info!("Parsing the first {} matching ePubs", matches.len());
+    matches
+}
 
-    fn header_prefix(&mut self, _level: usize) -> String {
-        String::new()
+/// Shows which book each ePub would be parsed as, and with which chapters,
+/// adding the ones that match nothing to `unmatched`.
+fn print_plan(
+    epub_files: &[EpubInput],
+    all_books: &[IndexableBook],
+    unmatched: &mut Vec<(String, String)>,
+) {
+    for input in epub_files {
+        let source = &input.source;
+        let path = &source.name();
+        let epub = match input.load() {
+            Ok(epub) => epub,
+            Err(why) => {
+                println!("{} -> {why}", path.display());
+                continue;
+            }
+        };
+        let Some((mut doc, epub_title)) = open_epub(epub, path) else {
+            println!("{} -> not a readable ePub", path.display());
+            continue;
+        };
+        let Some(book) = find_book(all_books, &epub_title, doc.unique_identifier.as_deref()) else {
+            println!("{} -> no match for {epub_title}", path.display());
+            unmatched.push((source.to_string(), epub_title));
+            continue;
+        };
+        for book in book.works() {
+            match validated_chapter_range(&book, &mut doc) {
+                Ok((first, last)) => println!(
+                    "{} -> {} -> chapters {first}..={last} -> skipping {:?}",
+                    path.display(),
+                    book.title,
+                    book.skippable_chapters
+                ),
+                Err(why) => println!("{} -> {} -> {why}", path.display(), book.title),
+            }
+        }
     }
+}
 
-    fn quote_prefix(&mut self) -> String {
-        String::new()
+fn write_books(
+    output: &mut Output,
+    mut sqlite: Option<&mut SqliteWriter>,
+    mut chapter_stats: Option<&mut BufWriter<File>>,
+    mut glossary: Option<&mut BufWriter<File>>,
+    mut toc: Option<&mut Vec<BookToc>>,
+    parsed_books: Vec<(Cow<'_, IndexableBook>, Result<SpooledBook, String>)>,
+) -> Result<Vec<(String, BookStats)>, Box<dyn Error>> {
+    let mut all_stats = Vec::new();
+    for (book, parsed) in parsed_books {
+        match parsed {
+            Ok(SpooledBook { parsed, records }) => {
+                let writer = output.writer_for(&book.title)?;
+                match sqlite.as_mut() {
+                    Some(sqlite) => sqlite.write_book_with(|insert| {
+                        for_each_spooled(records, |record| {
+                            writer.write(record)?;
+                            insert(record)
+                        })
+                    })?,
+                    None => for_each_spooled(records, |record| writer.write(record))?,
+                }
+                if let Some(out) = chapter_stats.as_mut() {
+                    for chapter in &parsed.chapters {
+                        serde_json::to_writer(&mut **out, chapter)?;
+                        out.write_all(b"\n")?;
+                    }
+                }
+                if let Some(out) = glossary.as_mut() {
+                    for chapter in &parsed.glossary {
+                        serde_json::to_writer(&mut **out, chapter)?;
+                        out.write_all(b"\n")?;
+                    }
+                }
+                if let Some(toc) = toc.as_mut() {
+                    toc.push(BookToc {
+                        book_title: book.title.clone(),
+                        chapters: parsed.toc,
+                    });
+                }
+                if parsed.stats.records < parsed.stats.chapters {
+                    warn!(
+                        "{} produced only {} records from {} chapters",
+                        book.title, parsed.stats.records, parsed.stats.chapters
+                    );
+                }
+                all_stats.push((book.title.clone(), parsed.stats));
+            }
+            Err(why) => error!("Skipping {}: {why}", book.title),
+        }
     }
+    Ok(all_stats)
+}
 
-    fn ordered_item_prefix(&mut self, _i: i64) -> String {
-        String::new()
+/// Lists the ePubs, and the titles they were read as, that matched no
+/// configured book, on stderr like the summary.
+fn print_unmatched(mut unmatched: Vec<(String, String)>) {
+    if unmatched.is_empty() {
+        eprintln!("Every ePub matched a configured book");
+        return;
     }
+    unmatched.sort_unstable();
+    eprintln!("ePubs that matched no configured book:");
+    for (source, epub_title) in &unmatched {
+        eprintln!("  {epub_title} ({source})");
+    }
+}
+
+/// A parsed book whose records are spooled to a temporary file as NDJSON
+/// rather than held in memory, so books can be parsed at once and still be
+/// written one after another in title order.
+struct SpooledBook {
+    /// Everything but the records.
+    parsed: ParsedBook,
+    records: File,
+}
 
-    fn unordered_item_prefix(&mut self) -> String {
-        String::new()
+/// Parses `book` with its records streamed out to a [`SpooledBook`].
+fn spool_book(
+    book: &IndexableBook,
+    doc: EpubDoc<EpubReader>,
+    epub_path: &Path,
+    options: &ParseOptions,
+) -> Result<SpooledBook, Box<dyn Error>> {
+    let mut spool = BufWriter::new(tempfile::tempfile()?);
+    let parsed = parse_book_with(book, doc, epub_path, options, |record| {
+        serde_json::to_writer(&mut spool, &record)?;
+        spool.write_all(b"\n")?;
+        Ok(())
+    })?;
+    let mut records = spool.into_inner().map_err(|it| it.into_error())?;
+    records.rewind()?;
+    Ok(SpooledBook { parsed, records })
+}
+
+/// Reads the records spooled by [`spool_book`] back in, one at a time.
+fn for_each_spooled(
+    records: File,
+    mut each: impl FnMut(&OutputSchema) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    for line in BufReader::new(records).lines() {
+        each(&serde_json::from_str(&line?)?)?;
     }
+    Ok(())
+}
 
-    fn finalise(&mut self, _links: Vec<String>) -> Vec<html2text::render::text_renderer::TaggedLine<Self::Annotation>> {
-        Vec::new()
+/// Printed to stderr so stdout can carry the records themselves.
+fn print_summary(all_stats: &[(String, BookStats)]) {
+    let mut total = BookStats::default();
+    let width = all_stats
+        .iter()
+        .map(|(title, _)| title.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Total".len());
+    eprintln!(
+        "{:<width$}  {:>8}  {:>8}  {:>9}",
+        "Book", "Chapters", "Records", "Words"
+    );
+    for (title, stats) in all_stats {
+        eprintln!(
+            "{title:<width$}  {:>8}  {:>8}  {:>9}",
+            stats.chapters, stats.records, stats.words
+        );
+        total += *stats;
     }
+    eprintln!(
+        "{:<width$}  {:>8}  {:>8}  {:>9}",
+        "Total", total.chapters, total.records, total.words
+    );
+}
 
-    fn make_subblock_decorator(&self) -> Self {
-        Self::new()
+const fn log_level(verbose: u8) -> Level {
+    match verbose {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
     }
 }
@@ -0,0 +1,80 @@
+//! The `manifest.json` written beside the output, recording how it was made.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+    time::SystemTime,
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// What the manifest is called, in the same directory as the output.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Everything that went into one run's output.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub tool_version: String,
+    /// When the run finished, in RFC 3339.
+    pub generated_at: String,
+    /// The `books.toml` that was read, or `built-in` for the compiled-in list.
+    pub config_source: String,
+    /// Every ePub found, whether or not it matched a book.
+    pub inputs: Vec<ManifestInput>,
+    pub books: Vec<ManifestBook>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestInput {
+    pub path: String,
+    /// SHA-256 of the whole file, in hex.
+    pub sha256: String,
+}
+
+/// A book that was parsed, and the spine range its records came from.
+#[derive(Debug, Serialize)]
+pub struct ManifestBook {
+    pub title: String,
+    pub path: String,
+    pub first_chapter_index: usize,
+    pub last_chapter_index: usize,
+    pub skippable_chapters: Vec<usize>,
+}
+
+impl Manifest {
+    /// A manifest stamped with this build's version and the current time.
+    /// Inputs and books are sorted so the same run lists them the same way.
+    pub fn new(
+        config_source: String,
+        mut inputs: Vec<ManifestInput>,
+        mut books: Vec<ManifestBook>,
+    ) -> Self {
+        inputs.sort_by(|a, b| a.path.cmp(&b.path));
+        books.sort_by(|a, b| a.title.cmp(&b.title).then_with(|| a.path.cmp(&b.path)));
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            generated_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            config_source,
+            inputs,
+            books,
+        }
+    }
+}
+
+/// The SHA-256 of the file at `path`, in hex.
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    sha256_hex(BufReader::new(File::open(path)?))
+}
+
+/// The SHA-256 of everything `reader` reads, in hex.
+pub(crate) fn sha256_hex(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
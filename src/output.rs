@@ -0,0 +1,508 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    record_id::{uuid_v5, RECORD_ID_NAMESPACE},
+    schema::{output_schema, schema_violations},
+    SectionType,
+};
+
+/// One searchable paragraph, as written to the output file.
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct OutputSchema {
+    /// A UUID that stays the same from run to run until the record's text
+    /// changes, for upserting; see [`record_id`].
+    pub id: String,
+    pub book_title: String,
+    /// The ePub's `dc:identifier`, like a UUID or ISBN, for keying records by
+    /// edition; a hash of the title when it has none.
+    pub book_id: String,
+    pub chapter_title: String,
+    /// The chapter's number, for sorting; unset for prologues, epilogues, and
+    /// other unnumbered sections.
+    pub chapter_number: Option<u32>,
+    /// The part the chapter is in, for books numbered like `Part 2, Chapter 3`.
+    pub part_number: Option<u32>,
+    /// What kind of section the chapter is, for the book outline.
+    pub section_type: SectionType,
+    pub searchable_text: String,
+    /// `searchable_text` lowercased with accents and typographic punctuation
+    /// folded to ASCII, so `Shallan’s` matches a search for `shallan's`.
+    pub search_key: String,
+    pub display_text: String,
+    /// Text of any footnotes referenced from `searchable_text`.
+    pub footnotes: Vec<String>,
+    /// Descriptions of the illustrations in the paragraph, from their alt text.
+    pub images: Vec<String>,
+    /// The CSS class of the paragraph in the ePub, like `Letter` or `Song`,
+    /// for setting in-world documents apart from the narration.
+    pub style_tag: Option<String>,
+    /// Set only on a chapter's epigraph record, to the epigraph's plain text,
+    /// without the attribution.
+    pub epigraph: Option<String>,
+    /// Who the epigraph is attributed to, from a last line like `—Collected
+    /// on Chachel, 1172`.
+    pub epigraph_source: Option<String>,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    /// Position within `series`; fractional for novellas set between books.
+    pub series_index: Option<f32>,
+    /// The ePub's `dc:language`, like `en` or `es-ES`; `en` when it doesn't say.
+    pub language: String,
+    /// Position of the paragraph within its chapter, counting from 0. Only
+    /// paragraphs that get records are counted.
+    pub paragraph_index: usize,
+    /// With sentence granularity, the sentence's position within its paragraph.
+    pub sentence_index: Option<usize>,
+    /// For a paragraph split for being over `max_words_per_record`, this
+    /// record's position among its pieces.
+    pub chunk_index: Option<usize>,
+    /// Where `searchable_text` starts in the chapter's prose, counted in
+    /// characters. The prose is the chapter's paragraphs one per line, each as
+    /// its whole-paragraph `searchable_text`, the same paragraphs
+    /// `display_text` is built from.
+    pub char_start: usize,
+    /// Where `searchable_text` ends in the chapter's prose, exclusive.
+    pub char_end: usize,
+    /// The ePub file the record was parsed from, for telling editions apart.
+    pub source_path: String,
+}
+
+/// Length of one chapter, for showing "~8 min read" alongside it.
+#[derive(Debug, Serialize)]
+pub struct ChapterSummary {
+    pub book_title: String,
+    pub chapter_title: String,
+    pub word_count: usize,
+    /// `word_count` at [`ParseOptions::words_per_minute`](crate::ParseOptions),
+    /// rounded up.
+    pub reading_minutes: usize,
+}
+
+/// The terms one chapter italicizes, like ship names and forms of magic, as
+/// a start on a glossary of the book.
+#[derive(Debug, Serialize)]
+pub struct ChapterGlossary {
+    pub book_title: String,
+    pub chapter_title: String,
+    /// Each distinct italicized span of up to a few words, in the order they
+    /// first appear; longer ones are usually thoughts or emphasis instead.
+    pub glossary_candidates: Vec<String>,
+}
+
+/// The outline of one book, for a navigation sidebar alongside the records.
+#[derive(Debug, Serialize)]
+pub struct BookToc {
+    pub book_title: String,
+    pub chapters: Vec<TocEntry>,
+}
+
+/// One chapter in a book's outline.
+#[derive(Debug, Serialize)]
+pub struct TocEntry {
+    pub chapter_title: String,
+    pub chapter_number: Option<u32>,
+    pub part_number: Option<u32>,
+    pub section_type: SectionType,
+    /// Where it sits in the ePub's spine.
+    pub spine_index: usize,
+    /// Position of the chapter's first record among the book's records,
+    /// counting from 0, or `None` if it has none.
+    pub first_record_index: Option<usize>,
+}
+
+/// How records are laid out in the output file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One JSON object per line
+    #[default]
+    Ndjson,
+    /// A single JSON array holding every record
+    JsonArray,
+    /// Comma-separated values with a header row
+    Csv,
+    /// A JSON array whose records carry a stable `id` primary key
+    Meilisearch,
+    /// Elasticsearch `_bulk` NDJSON: an index action line before each record
+    EsBulk,
+    /// Typesense JSONL: one record per line with a stable string `id`
+    Typesense,
+}
+
+impl OutputFormat {
+    /// Whether the whole output is one JSON array, which can't be added to.
+    fn is_array(self) -> bool {
+        matches!(self, Self::JsonArray | Self::Meilisearch)
+    }
+
+    /// Whether each record has to start with its `id`, whatever `[fields]`
+    /// says.
+    fn has_id(self) -> bool {
+        matches!(self, Self::Meilisearch | Self::Typesense)
+    }
+
+    /// The file extension for output written in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            _ => "json",
+        }
+    }
+}
+
+/// The name `format` goes by on the command line.
+fn format_name(format: OutputFormat) -> String {
+    format
+        .to_possible_value()
+        .map_or_else(|| format!("{format:?}"), |it| it.get_name().to_string())
+}
+
+/// Which record fields are written and what they're called, as set in the
+/// `[fields]` table of `books.toml`. By default every field is written under
+/// its own name.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct FieldMapping {
+    /// Output names for fields, like `searchable_text = "content"`.
+    #[serde(default)]
+    pub rename: HashMap<String, String>,
+    /// Fields left out of every record.
+    #[serde(default)]
+    pub omit: Vec<String>,
+}
+
+impl FieldMapping {
+    /// Checks that every field named is a real one and that no two end up
+    /// with the same name.
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        let fields = to_fields(&OutputSchema::default())?;
+        if let Some(unknown) = self
+            .rename
+            .keys()
+            .chain(&self.omit)
+            .find(|it| !fields.contains_key(*it))
+        {
+            return Err(format!("there's no {unknown} field to rename or omit").into());
+        }
+        let mut names = HashSet::new();
+        for name in fields.keys().filter_map(|it| self.name_of(it)) {
+            if !names.insert(name) {
+                return Err(format!("more than one field would be written as {name}").into());
+            }
+        }
+        Ok(())
+    }
+
+    /// What `field` is written as, or `None` if it's left out.
+    pub fn name_of<'a>(&'a self, field: &'a str) -> Option<&'a str> {
+        if self.omit.iter().any(|it| it == field) {
+            return None;
+        }
+        Some(self.rename.get(field).map_or(field, String::as_str))
+    }
+
+    /// `fields` renamed and filtered, in their original order.
+    fn apply(&self, fields: Map<String, Value>) -> Map<String, Value> {
+        if self.rename.is_empty() && self.omit.is_empty() {
+            return fields;
+        }
+        fields
+            .into_iter()
+            .filter_map(|(field, value)| Some((self.name_of(&field)?.to_string(), value)))
+            .collect()
+    }
+}
+
+/// The `book_title` of every record in an earlier run's output, which was
+/// written with `fields`.
+pub fn existing_book_titles(
+    path: &Path,
+    format: OutputFormat,
+    fields: &FieldMapping,
+) -> Result<HashSet<String>, Box<dyn Error>> {
+    let title_field = fields
+        .name_of("book_title")
+        .ok_or("can't tell which books are already written when book_title is omitted")?;
+    let mut titles = HashSet::new();
+    match format {
+        OutputFormat::Csv => {
+            let mut csv = csv::Reader::from_path(path)?;
+            let column = csv
+                .headers()?
+                .iter()
+                .position(|it| it == title_field)
+                .ok_or_else(|| format!("the existing CSV has no {title_field} column"))?;
+            for row in csv.records() {
+                if let Some(title) = row?.get(column) {
+                    titles.insert(title.to_string());
+                }
+            }
+        }
+        _ if format.is_array() => {
+            return Err(format!(
+                "can't add records to existing {} output",
+                format_name(format)
+            )
+            .into())
+        }
+        // Bulk action lines have no book_title, so they drop out on their own
+        _ => {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record: Value = serde_json::from_str(&line)?;
+                if let Some(title) = record.get(title_field).and_then(Value::as_str) {
+                    titles.insert(title.to_string());
+                }
+            }
+        }
+    }
+    Ok(titles)
+}
+
+/// The Elasticsearch index bulk actions target unless told otherwise.
+pub const DEFAULT_ES_INDEX: &str = "cosmere";
+
+/// Serializes records one at a time in the chosen [`OutputFormat`].
+///
+/// Call [`RecordWriter::finish`] once everything is written, even after an
+/// error, so formats that need closing stay well-formed.
+pub struct RecordWriter<W: Write> {
+    sink: Sink<W>,
+    format: OutputFormat,
+    written: usize,
+    es_index: String,
+    /// Set to check every record against the output schema before writing it.
+    schema: Option<Value>,
+    fields: FieldMapping,
+    /// The CSV header still has to be written, which waits for
+    /// [`RecordWriter::with_fields`] since it's named by the mapping.
+    header_pending: bool,
+}
+
+enum Sink<W: Write> {
+    Json(W),
+    Csv(Box<csv::Writer<W>>),
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(out: W, format: OutputFormat) -> Result<Self, Box<dyn Error>> {
+        Self::open(out, format, false)
+    }
+
+    /// Writes after the records an earlier run left in `out`, which must be
+    /// positioned at its end. Only line-based formats can be added to.
+    pub fn append(out: W, format: OutputFormat) -> Result<Self, Box<dyn Error>> {
+        if format.is_array() {
+            return Err(format!(
+                "can't add records to existing {} output",
+                format_name(format)
+            )
+            .into());
+        }
+        Self::open(out, format, true)
+    }
+
+    fn open(mut out: W, format: OutputFormat, append: bool) -> Result<Self, Box<dyn Error>> {
+        let sink = match format {
+            OutputFormat::Ndjson | OutputFormat::EsBulk | OutputFormat::Typesense => {
+                Sink::Json(out)
+            }
+            OutputFormat::JsonArray | OutputFormat::Meilisearch => {
+                out.write_all(b"[")?;
+                Sink::Json(out)
+            }
+            OutputFormat::Csv => Sink::Csv(Box::new(
+                csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(out),
+            )),
+        };
+        Ok(Self {
+            sink,
+            format,
+            written: 0,
+            es_index: DEFAULT_ES_INDEX.to_string(),
+            schema: None,
+            fields: FieldMapping::default(),
+            header_pending: format == OutputFormat::Csv && !append,
+        })
+    }
+
+    /// Sets the index named in each [`OutputFormat::EsBulk`] action line.
+    pub fn with_es_index(mut self, index: impl Into<String>) -> Self {
+        self.es_index = index.into();
+        self
+    }
+
+    /// Checks each record against [`output_schema`] before writing it, and
+    /// fails on the first one that doesn't match.
+    pub fn with_validation(mut self, validate: bool) -> Self {
+        self.schema = validate.then(output_schema);
+        self
+    }
+
+    /// Renames and leaves out fields as `fields` says. Validation still checks
+    /// records as they were before.
+    pub fn with_fields(mut self, fields: FieldMapping) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// A record's fields as they'll be written.
+    fn fields_of(&self, record: &OutputSchema) -> Result<Map<String, Value>, Box<dyn Error>> {
+        Ok(self.fields.apply(to_fields(record)?))
+    }
+
+    /// Writes the CSV header if it hasn't been yet, so even an empty run has
+    /// one.
+    fn write_header(&mut self) -> Result<(), Box<dyn Error>> {
+        if std::mem::take(&mut self.header_pending) {
+            let header = self.fields_of(&OutputSchema::default())?;
+            if let Sink::Csv(csv) = &mut self.sink {
+                csv.write_record(header.keys())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, record: &OutputSchema) -> Result<(), Box<dyn Error>> {
+        if let Some(schema) = &self.schema {
+            let violations = schema_violations(schema, &serde_json::to_value(record)?);
+            if !violations.is_empty() {
+                return Err(format!(
+                    "record {} doesn't match the output schema: {}",
+                    self.written + 1,
+                    violations.join("; ")
+                )
+                .into());
+            }
+        }
+        self.write_header()?;
+        let mut fields = self.fields_of(record)?;
+        if self.format.has_id() {
+            let mut document = Map::new();
+            document.insert("id".to_string(), Value::String(record.id.clone()));
+            document.extend(fields);
+            fields = document;
+        }
+        match &mut self.sink {
+            Sink::Csv(csv) => csv.write_record(fields.values().map(csv_cell))?,
+            Sink::Json(out) => match self.format {
+                OutputFormat::JsonArray | OutputFormat::Meilisearch => {
+                    if self.written > 0 {
+                        out.write_all(b",")?;
+                    }
+                    out.write_all(b"\n")?;
+                    serde_json::to_writer(out, &fields)?;
+                }
+                _ => {
+                    if self.format == OutputFormat::EsBulk {
+                        let action = serde_json::json!({ "index": { "_index": self.es_index } });
+                        serde_json::to_writer(&mut *out, &action)?;
+                        out.write_all(b"\n")?;
+                    }
+                    serde_json::to_writer(&mut *out, &fields)?;
+                    out.write_all(b"\n")?;
+                }
+            },
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Closes out the format and flushes, handing back the underlying writer.
+    pub fn finish(mut self) -> Result<W, Box<dyn Error>> {
+        self.write_header()?;
+        let mut out = match self.sink {
+            Sink::Json(out) => out,
+            Sink::Csv(csv) => csv.into_inner().map_err(|why| why.into_error())?,
+        };
+        if self.format.is_array() {
+            out.write_all(b"\n]\n")?;
+        }
+        out.flush()?;
+        Ok(out)
+    }
+}
+
+/// A primary key that's the same on every run and every machine until the
+/// record's text changes: the version 5 UUID, in [`RECORD_ID_NAMESPACE`], of
+/// its `book_id`, `chapter_number` (empty when it has none),
+/// `paragraph_index`, and `searchable_text`, joined with NUL bytes. After
+/// those come `book_title`, which tells apart the works of an omnibus sharing
+/// a `book_id`, then whichever of `chapter_title` (only for an unnumbered
+/// chapter), `part_number`, `sentence_index`, and `chunk_index` are set,
+/// each as `name=value`, so no two records of a book share an id.
+pub fn record_id(record: &OutputSchema) -> String {
+    let chapter_number = record
+        .chapter_number
+        .map(|it| it.to_string())
+        .unwrap_or_default();
+    // Separators keep "ab" + "c" from naming the same as "a" + "bc"
+    let mut name = [
+        record.book_id.as_str(),
+        &chapter_number,
+        &record.paragraph_index.to_string(),
+        &record.searchable_text,
+        &record.book_title,
+    ]
+    .join("\0");
+    if record.chapter_number.is_none() {
+        name.push_str(&format!("\0chapter_title={}", record.chapter_title));
+    }
+    let positions = [
+        ("part_number", record.part_number.map(|it| it as usize)),
+        ("sentence_index", record.sentence_index),
+        ("chunk_index", record.chunk_index),
+    ];
+    for (field, value) in positions {
+        if let Some(value) = value {
+            name.push_str(&format!("\0{field}={value}"));
+        }
+    }
+    uuid_v5(RECORD_ID_NAMESPACE, name.as_bytes())
+}
+
+/// The [`OutputSchema::book_id`] for an ePub without an identifier: the first
+/// 16 bytes of the SHA-256 of the book's title, in hex.
+pub(crate) fn title_id(title: &str) -> String {
+    short_hex(&Sha256::digest(title.as_bytes()))
+}
+
+fn short_hex(digest: &[u8]) -> String {
+    digest[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// A record as an ordered map of field name to value.
+pub(crate) fn to_fields(record: &OutputSchema) -> Result<Map<String, Value>, Box<dyn Error>> {
+    match serde_json::to_value(record)? {
+        Value::Object(fields) => Ok(fields),
+        _ => Err("records always serialize to objects".into()),
+    }
+}
+
+/// Strings go into CSV as-is; lists and anything else nested are written as
+/// JSON so they survive a round trip.
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
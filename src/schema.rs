@@ -0,0 +1,141 @@
+//! The JSON Schema for output records, and a check that records still match it.
+
+use schemars::schema_for;
+use serde_json::Value;
+
+use crate::OutputSchema;
+
+/// The JSON Schema describing one [`OutputSchema`] record.
+pub fn output_schema() -> Value {
+    serde_json::to_value(schema_for!(OutputSchema)).expect("schemas always serialize")
+}
+
+/// Every way `value` strays from `schema`, each starting with where in the
+/// record it happened. Empty when it matches.
+///
+/// Only the parts of JSON Schema that the record schema actually uses are
+/// checked: `type`, `enum`, `properties`, `required`, `items`, `minimum`,
+/// `allOf`, `oneOf`, and `$ref`s into the schema's own `definitions`. Fields
+/// the schema doesn't list count as a mismatch too, since they mean the schema
+/// has fallen behind the records.
+pub fn schema_violations(schema: &Value, value: &Value) -> Vec<String> {
+    let mut violations = Vec::new();
+    check(schema, schema, value, "record", &mut violations);
+    violations
+}
+
+fn check(root: &Value, schema: &Value, value: &Value, at: &str, violations: &mut Vec<String>) {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match resolve(root, reference) {
+            Some(it) => check(root, it, value, at, violations),
+            None => violations.push(format!(
+                "{at} refers to {reference}, which isn't in the schema"
+            )),
+        }
+    }
+
+    for part in schema
+        .get("allOf")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        check(root, part, value, at, violations);
+    }
+
+    if let Some(options) = schema.get("oneOf").and_then(Value::as_array) {
+        let matching = options
+            .iter()
+            .filter(|it| {
+                let mut ignored = Vec::new();
+                check(root, it, value, at, &mut ignored);
+                ignored.is_empty()
+            })
+            .count();
+        if matching != 1 {
+            violations.push(format!(
+                "{at} should match exactly one of {} schemas, matches {matching}",
+                options.len()
+            ));
+        }
+    }
+
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(it) => vec![it.as_str()],
+            Value::Array(them) => them.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.iter().any(|it| has_type(value, it)) {
+            violations.push(format!(
+                "{at} should be {}, found {value}",
+                allowed.join(" or ")
+            ));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            violations.push(format!(
+                "{at} should be one of {}, found {value}",
+                allowed.join(", ")
+            ));
+        }
+    }
+
+    if let (Some(minimum), Some(number)) = (
+        schema.get("minimum").and_then(Value::as_f64),
+        value.as_f64(),
+    ) {
+        if number < minimum {
+            violations.push(format!("{at} should be at least {minimum}, found {number}"));
+        }
+    }
+
+    if let Value::Object(fields) = value {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for required in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !fields.contains_key(required) {
+                violations.push(format!("{at} is missing {required}"));
+            }
+        }
+        for (name, field) in fields {
+            match properties.and_then(|it| it.get(name)) {
+                Some(field_schema) => check(root, field_schema, field, name, violations),
+                None => violations.push(format!("{at} has {name}, which isn't in the schema")),
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check(root, item_schema, item, &format!("{at}[{i}]"), violations);
+        }
+    }
+}
+
+/// The schema a `#/definitions/...` reference points to in `root`.
+fn resolve<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    root.pointer(reference.strip_prefix('#')?)
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        _ => true,
+    }
+}
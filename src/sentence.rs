@@ -0,0 +1,225 @@
+//! Splitting a paragraph's plain text into sentences.
+
+/// Words that end in a period without ending the sentence, lowercased and
+/// without the period. `No.` isn't one: in dialogue it's nearly always the
+/// word, and `No. 4` is kept together anyway by the digit after it.
+const ABBREVIATIONS: [&str; 21] = [
+    "mr", "mrs", "ms", "dr", "st", "mt", "jr", "sr", "vs", "etc", "lt", "capt", "col", "gen",
+    "sgt", "prof", "rev", "vol", "ft", "e.g", "i.e",
+];
+
+/// Verbs that, right after a quote and a name, mean the quote's sentence is
+/// still going: `"Now!" Wayne shouted.`
+const SPEECH_VERBS: [&str; 16] = [
+    "said",
+    "asked",
+    "shouted",
+    "whispered",
+    "replied",
+    "called",
+    "cried",
+    "yelled",
+    "muttered",
+    "added",
+    "snapped",
+    "demanded",
+    "exclaimed",
+    "answered",
+    "murmured",
+    "says",
+];
+
+/// Marks that can close a quote or aside right after the sentence's own end.
+const CLOSERS: [char; 6] = ['"', '\'', '”', '’', ')', ']'];
+
+/// Marks that can open a quote, so a sentence can start with one.
+const OPENERS: [char; 5] = ['"', '\'', '“', '‘', '('];
+
+/// Splits `text` into its sentences, trimmed.
+///
+/// A sentence ends at `.`, `!`, or `?` (plus any closing quotes) when the next
+/// word starts like a sentence does, with a capital or an opening quote. That
+/// keeps `"Stop!" he said.` together, as does a named speaker like `"Stop!"
+/// Wax said.` Periods after an abbreviation like `Mr.` or an initial like `T.`
+/// don't count. An ellipsis only ends a sentence when
+/// new dialogue opens right after it, so a speaker trailing off mid-line
+/// (`"I… I don't know."`) stays one sentence. Markup tags like `</em>` around
+/// where a sentence ends are stepped over.
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let (_, c) = chars[i];
+        if !matches!(c, '.' | '!' | '?' | '…') {
+            i += 1;
+            continue;
+        }
+
+        // Take the whole run of terminators and closers, like `?!”`, along
+        // with any closing tags like `</em>` mixed in
+        let mut end = i + 1;
+        while end < chars.len() {
+            if matches!(chars[end].1, '.' | '!' | '?' | '…') || CLOSERS.contains(&chars[end].1) {
+                end += 1;
+            } else if let Some(after_tag) = tag_end(&chars, end) {
+                end = after_tag;
+            } else {
+                break;
+            }
+        }
+        let Some(next) = chars[end..].iter().position(|(_, it)| !it.is_whitespace()) else {
+            break;
+        };
+        let next = end + next;
+        if next == end {
+            // Not followed by a space, like `3.5` or `e.g.`
+            i = end;
+            continue;
+        }
+
+        let run: String = chars[i..end]
+            .iter()
+            .map(|(_, it)| it)
+            .filter(|it| !matches!(it, '<' | '>' | '/') && !it.is_alphanumeric())
+            .collect();
+        // The next word's first letter, past any opening tag like `<em>`
+        let mut word_start = next;
+        while let Some(after_tag) = tag_end(&chars, word_start) {
+            word_start = after_tag;
+        }
+        let Some(&(_, next_char)) = chars.get(word_start) else {
+            break;
+        };
+        let ends_sentence = if run.starts_with('…') || run.starts_with("...") {
+            OPENERS.contains(&next_char)
+        } else {
+            (next_char.is_uppercase() || OPENERS.contains(&next_char))
+                && !(c == '.' && ends_with_abbreviation(&text[start..chars[i].0]))
+                && !(run.ends_with(CLOSERS) && is_attribution(&text[chars[next].0..]))
+        };
+
+        if ends_sentence {
+            let cut = chars[end - 1].0 + chars[end - 1].1.len_utf8();
+            push_trimmed(&mut sentences, &text[start..cut]);
+            start = chars[next].0;
+        }
+        i = next;
+    }
+    push_trimmed(&mut sentences, &text[start..]);
+    sentences
+}
+
+/// Groups `text`'s sentences into pieces of at most `max_words` words each,
+/// for paragraphs too long to be one record. A single sentence over the limit
+/// still gets a piece to itself rather than being cut mid-sentence.
+pub fn chunk_sentences(text: &str, max_words: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_words = 0;
+    for sentence in split_sentences(text) {
+        let words = sentence.split_whitespace().count();
+        if chunk_words > 0 && chunk_words + words > max_words {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_words = 0;
+        }
+        if !chunk.is_empty() {
+            chunk.push(' ');
+        }
+        chunk.push_str(&sentence);
+        chunk_words += words;
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Where the markup tag starting at `chars[at]` ends, if one does.
+fn tag_end(chars: &[(usize, char)], at: usize) -> Option<usize> {
+    if chars.get(at)?.1 != '<' {
+        return None;
+    }
+    chars[at..]
+        .iter()
+        .position(|(_, it)| *it == '>')
+        .map(|len| at + len + 1)
+}
+
+/// Whether `after` a closing quote starts with a speaker and a speech verb.
+fn is_attribution(after: &str) -> bool {
+    let mut words = after
+        .split_whitespace()
+        .map(|it| it.trim_end_matches(|c: char| !c.is_alphabetic()));
+    words.next().is_some_and(|name| !name.is_empty())
+        && words
+            .next()
+            .is_some_and(|verb| SPEECH_VERBS.contains(&verb))
+}
+
+/// Whether the text before a period ends in an abbreviation or an initial.
+fn ends_with_abbreviation(before: &str) -> bool {
+    let word = before
+        .rsplit(char::is_whitespace)
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches(|it| OPENERS.contains(&it))
+        .to_lowercase();
+    let is_initial = word.chars().count() == 1 && word.chars().all(char::is_alphabetic);
+    is_initial || ABBREVIATIONS.contains(&word.as_str())
+}
+
+fn push_trimmed(sentences: &mut Vec<String>, sentence: &str) {
+    let sentence = sentence.trim();
+    if !sentence.is_empty() {
+        sentences.push(sentence.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_sentences;
+
+    #[test]
+    fn titles_dont_end_sentences() {
+        assert_eq!(
+            split_sentences("Mr. Harms waved. Dr. Vasher didn't."),
+            ["Mr. Harms waved.", "Dr. Vasher didn't."]
+        );
+    }
+
+    #[test]
+    fn ellipses_mid_quote_dont_end_sentences() {
+        assert_eq!(
+            split_sentences("“I… I don’t know.” Shallan looked away."),
+            ["“I… I don’t know.”", "Shallan looked away."]
+        );
+        assert_eq!(
+            split_sentences("He trailed off… “Wait.”"),
+            ["He trailed off…", "“Wait.”"]
+        );
+    }
+
+    #[test]
+    fn no_is_a_word() {
+        assert_eq!(
+            split_sentences("“No.” Kaladin turned away."),
+            ["“No.”", "Kaladin turned away."]
+        );
+        assert_eq!(
+            split_sentences("He said no. Then he left."),
+            ["He said no.", "Then he left."]
+        );
+        assert_eq!(split_sentences("Bridge No. 4 ran."), ["Bridge No. 4 ran."]);
+    }
+
+    #[test]
+    fn attributions_stay_with_their_quote() {
+        assert_eq!(
+            split_sentences("“Now!” Wayne shouted. Then he ran."),
+            ["“Now!” Wayne shouted.", "Then he ran."]
+        );
+        assert_eq!(split_sentences("“Stop!” he said."), ["“Stop!” he said."]);
+    }
+}
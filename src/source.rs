@@ -0,0 +1,224 @@
+//! Where an ePub is read from: a file, a member of a zip archive like a
+//! whole series zipped up, or a URL.
+
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+use epub::doc::{DocError, EpubDoc};
+use tempfile::NamedTempFile;
+use zip::ZipArchive;
+
+use crate::manifest::{file_sha256, sha256_hex};
+
+/// An input the ePubs are found in, before it's read.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EpubSource {
+    File(PathBuf),
+    /// `member` is the ePub's path inside the zip file at `archive`.
+    ArchiveMember {
+        archive: PathBuf,
+        member: String,
+    },
+    /// An `http://` or `https://` URL, downloaded to a temporary file.
+    Url(String),
+}
+
+impl EpubSource {
+    /// `input` as a URL, if it is one.
+    pub fn url(input: &str) -> Option<Self> {
+        let lowercase = input.to_ascii_lowercase();
+        (lowercase.starts_with("http://") || lowercase.starts_with("https://"))
+            .then(|| Self::Url(input.to_string()))
+    }
+
+    /// `path` as a member of a zip archive, like `series.zip/mistborn.epub`,
+    /// if part of it names a zip file.
+    pub fn archive_member(path: &Path) -> Option<Self> {
+        let archive = path
+            .ancestors()
+            .skip(1)
+            .find(|it| is_zip(it) && it.is_file())?;
+        let member = path.strip_prefix(archive).ok()?;
+        let member = member
+            .components()
+            .map(|it| it.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        Some(Self::ArchiveMember {
+            archive: archive.to_path_buf(),
+            member,
+        })
+    }
+
+    /// Every ePub inside the zip file at `archive`, in name order.
+    pub fn archive_members(archive: &Path) -> Result<Vec<Self>, Box<dyn Error>> {
+        let zip = ZipArchive::new(BufReader::new(File::open(archive)?))?;
+        let mut members: Vec<&str> = zip
+            .file_names()
+            .filter(|it| !it.ends_with('/') && is_epub(Path::new(it)))
+            .collect();
+        members.sort_unstable();
+        Ok(members
+            .into_iter()
+            .map(|member| Self::ArchiveMember {
+                archive: archive.to_path_buf(),
+                member: member.to_string(),
+            })
+            .collect())
+    }
+
+    /// The file on disk this is read from: the archive, for a member. `None`
+    /// for a URL.
+    pub fn local_file(&self) -> Option<&Path> {
+        match self {
+            Self::File(path) | Self::ArchiveMember { archive: path, .. } => Some(path),
+            Self::Url(_) => None,
+        }
+    }
+
+    /// What the source goes by in records and logs, as a path so its file
+    /// name can stand in for a missing title.
+    pub fn name(&self) -> PathBuf {
+        PathBuf::from(self.to_string())
+    }
+
+    /// Reads the ePub in, downloading or unzipping it as needed, so it can be
+    /// opened as many times as it takes.
+    pub fn load(&self) -> Result<LoadedEpub, Box<dyn Error>> {
+        match self {
+            Self::File(path) => Ok(LoadedEpub::File(path.clone())),
+            Self::ArchiveMember { archive, member } => {
+                let mut zip = ZipArchive::new(BufReader::new(File::open(archive)?))?;
+                let mut member = zip.by_name(member)?;
+                let mut bytes = Vec::with_capacity(member.size().try_into().unwrap_or(0));
+                member.read_to_end(&mut bytes)?;
+                Ok(LoadedEpub::Bytes(bytes.into()))
+            }
+            Self::Url(url) => {
+                let mut response = minreq::get(url)
+                    .send_lazy()
+                    .map_err(|why| format!("couldn't download it: {why}"))?;
+                if !(200..300).contains(&response.status_code) {
+                    return Err(format!(
+                        "couldn't download it: {} {}",
+                        response.status_code, response.reason_phrase
+                    )
+                    .into());
+                }
+                let mut file = NamedTempFile::new()?;
+                io::copy(&mut response, file.as_file_mut())
+                    .map_err(|why| format!("couldn't download it: {why}"))?;
+                Ok(LoadedEpub::TempFile(Arc::new(file)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for EpubSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::ArchiveMember { archive, member } => {
+                write!(f, "{}", archive.join(member).display())
+            }
+            Self::Url(url) => f.write_str(url),
+        }
+    }
+}
+
+/// An ePub that's been read in from its [`EpubSource`].
+#[derive(Clone, Debug)]
+pub enum LoadedEpub {
+    File(PathBuf),
+    Bytes(Arc<[u8]>),
+    /// A download, deleted once the last clone is dropped.
+    TempFile(Arc<NamedTempFile>),
+}
+
+impl LoadedEpub {
+    /// Opens the ePub from its start.
+    pub fn open(&self) -> Result<EpubDoc<EpubReader>, DocError> {
+        let reader = match self {
+            Self::File(path) => EpubReader::File(BufReader::new(File::open(path)?)),
+            Self::TempFile(file) => EpubReader::File(BufReader::new(file.reopen()?)),
+            Self::Bytes(bytes) => EpubReader::Bytes(Cursor::new(Arc::clone(bytes))),
+        };
+        EpubDoc::from_reader(reader)
+    }
+
+    /// The SHA-256 of the whole ePub, in hex.
+    pub fn sha256(&self) -> io::Result<String> {
+        match self {
+            Self::File(path) => file_sha256(path),
+            Self::TempFile(file) => file_sha256(file.path()),
+            Self::Bytes(bytes) => sha256_hex(&bytes[..]),
+        }
+    }
+}
+
+/// An [`EpubSource`] that's read in the first time it's needed and kept, so a
+/// URL is downloaded once however many times the ePub is opened.
+#[derive(Debug)]
+pub struct EpubInput {
+    pub source: EpubSource,
+    loaded: OnceLock<Result<LoadedEpub, String>>,
+}
+
+impl EpubInput {
+    pub fn new(source: EpubSource) -> Self {
+        Self {
+            source,
+            loaded: OnceLock::new(),
+        }
+    }
+
+    /// The ePub, read in with [`EpubSource::load`] the first time.
+    pub fn load(&self) -> Result<&LoadedEpub, Box<dyn Error>> {
+        self.loaded
+            .get_or_init(|| self.source.load().map_err(|why| why.to_string()))
+            .as_ref()
+            .map_err(|why| why.clone().into())
+    }
+}
+
+/// Reads a [`LoadedEpub`], whether it's a file or held in memory.
+pub enum EpubReader {
+    File(BufReader<File>),
+    Bytes(Cursor<Arc<[u8]>>),
+}
+
+impl Read for EpubReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::File(it) => it.read(buf),
+            Self::Bytes(it) => it.read(buf),
+        }
+    }
+}
+
+impl Seek for EpubReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::File(it) => it.seek(pos),
+            Self::Bytes(it) => it.seek(pos),
+        }
+    }
+}
+
+/// Whether `path` is named like an ePub.
+pub fn is_epub(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("epub"))
+}
+
+/// Whether `path` is named like a zip archive.
+pub fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
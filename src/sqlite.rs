@@ -0,0 +1,135 @@
+use std::{error::Error, path::Path};
+
+use rusqlite::{params_from_iter, types::Value as SqlValue, Connection};
+use serde_json::Value;
+
+use crate::{output::to_fields, output_schema, OutputSchema};
+
+/// The column the records' own UUID [`OutputSchema::id`] goes in, leaving `id`
+/// for the table's integer key.
+const RECORD_ID_COLUMN: &str = "record_id";
+
+/// Writes records into a SQLite table with one column per [`OutputSchema`]
+/// field, for loading straight into a full-text search index. List fields are
+/// stored as JSON text, and records already in the table are updated in place
+/// when their `record_id` comes up again.
+pub struct SqliteWriter {
+    conn: Connection,
+    columns: Vec<String>,
+}
+
+impl SqliteWriter {
+    /// Opens the database at `path`, replacing any records from a previous run.
+    pub fn create(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::open(path, true)
+    }
+
+    /// Opens the database at `path`, keeping records from previous runs.
+    pub fn append(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::open(path, false)
+    }
+
+    fn open(path: &Path, replace: bool) -> Result<Self, Box<dyn Error>> {
+        let conn = Connection::open(path)?;
+        let schema = output_schema();
+        let mut definitions = vec![String::from("id INTEGER PRIMARY KEY AUTOINCREMENT")];
+        let mut columns = Vec::new();
+        for (name, _) in to_fields(&OutputSchema::default())? {
+            let column = if name == "id" {
+                RECORD_ID_COLUMN.to_owned()
+            } else {
+                name.clone()
+            };
+            let mut definition = format!("{column} {}", column_type(&schema, &name));
+            if name == "id" {
+                definition.push_str(" UNIQUE");
+            }
+            definitions.push(definition);
+            columns.push(column);
+        }
+        if replace {
+            conn.execute("DROP TABLE IF EXISTS paragraphs", [])?;
+        }
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS paragraphs ({});",
+            definitions.join(", ")
+        ))?;
+        Ok(Self { conn, columns })
+    }
+
+    /// Inserts one book's records in a single transaction.
+    pub fn write_book(&mut self, records: &[OutputSchema]) -> Result<(), Box<dyn Error>> {
+        self.write_book_with(|insert| records.iter().try_for_each(insert))
+    }
+
+    /// Like [`write_book`](Self::write_book), for records that come one at a
+    /// time: `records` is handed a function that inserts one, and calls it for
+    /// each in turn.
+    pub fn write_book_with(
+        &mut self,
+        records: impl FnOnce(
+            &mut dyn FnMut(&OutputSchema) -> Result<(), Box<dyn Error>>,
+        ) -> Result<(), Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let placeholders: Vec<String> = (1..=self.columns.len()).map(|i| format!("?{i}")).collect();
+        let tx = self.conn.transaction()?;
+        {
+            let updates: Vec<String> = self
+                .columns
+                .iter()
+                .filter(|it| *it != RECORD_ID_COLUMN)
+                .map(|it| format!("{it} = excluded.{it}"))
+                .collect();
+            let mut insert = tx.prepare(&format!(
+                "INSERT INTO paragraphs ({}) VALUES ({}) ON CONFLICT ({RECORD_ID_COLUMN}) DO UPDATE SET {}",
+                self.columns.join(", "),
+                placeholders.join(", "),
+                updates.join(", ")
+            ))?;
+            records(&mut |record| {
+                let values = to_fields(record)?
+                    .into_iter()
+                    .map(|(_, value)| to_sql(value));
+                insert.execute(params_from_iter(values))?;
+                Ok(())
+            })?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// The SQLite type of the column for the record field `name`, from its type
+/// in the record's JSON Schema, `NOT NULL` unless the field is optional.
+fn column_type(schema: &Value, name: &str) -> String {
+    let field = &schema["properties"][name];
+    let types: Vec<&str> = match &field["type"] {
+        Value::String(it) => vec![it.as_str()],
+        Value::Array(them) => them.iter().filter_map(Value::as_str).collect(),
+        // Enums like `section_type` are written as their names
+        _ => vec!["string"],
+    };
+    let sql_type = match types.iter().find(|it| **it != "null") {
+        Some(&"integer" | &"boolean") => "INTEGER",
+        Some(&"number") => "REAL",
+        _ => "TEXT",
+    };
+    if types.contains(&"null") {
+        sql_type.to_owned()
+    } else {
+        format!("{sql_type} NOT NULL")
+    }
+}
+
+fn to_sql(value: Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(it) => SqlValue::Integer(i64::from(it)),
+        Value::Number(it) => it.as_i64().map_or_else(
+            || SqlValue::Real(it.as_f64().unwrap_or_default()),
+            SqlValue::Integer,
+        ),
+        Value::String(it) => SqlValue::Text(it),
+        nested @ (Value::Array(_) | Value::Object(_)) => SqlValue::Text(nested.to_string()),
+    }
+}
@@ -0,0 +1,58 @@
+//! Running with `--append` adds to the output of an earlier run.
+
+mod common;
+
+use std::{fs, io::Read, process::Command};
+
+use common::epub;
+use flate2::read::MultiGzDecoder;
+use tempfile::TempDir;
+
+const CONFIG: &str = r#"
+[[book]]
+title = "Tress of the Emerald Sea"
+first_chapter_index = 0
+last_chapter_index = 0
+skippable_chapters = []
+"#;
+
+#[test]
+fn appended_gzip_output_decompresses_whole() {
+    let dir = TempDir::new().expect("temp dir is made");
+    let book = epub(
+        "Tress of the Emerald Sea",
+        &["<p>Tress lived on a rock.</p>"],
+        "",
+    );
+    fs::write(dir.path().join("tress.epub"), book).unwrap();
+    fs::write(dir.path().join("books.toml"), CONFIG).unwrap();
+    for _ in 0..2 {
+        let status = Command::new(env!("CARGO_BIN_EXE_cosmere_epub_parser"))
+            .current_dir(dir.path())
+            .args(["tress.epub", "--config", "books.toml", "-o", "out.ndjson"])
+            .args([
+                "--format",
+                "ndjson",
+                "--gzip",
+                "--append",
+                "--no-manifest",
+                "-q",
+            ])
+            .status()
+            .expect("the binary runs");
+        assert!(status.success());
+    }
+    let file = fs::File::open(dir.path().join("out.ndjson.gz")).unwrap();
+    let mut records = String::new();
+    MultiGzDecoder::new(file)
+        .read_to_string(&mut records)
+        .expect("the output is valid gzip");
+    let texts: Vec<String> = records
+        .lines()
+        .map(|line| {
+            let record: serde_json::Value = serde_json::from_str(line).expect("a JSON record");
+            record["searchable_text"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    assert_eq!(texts, ["Tress lived on a rock.", "Tress lived on a rock."]);
+}
@@ -0,0 +1,53 @@
+//! The first and last paragraphs of a chapter have context on one side only,
+//! but still get records of their own.
+
+mod common;
+
+use common::{book, parse};
+use cosmere_epub_parser::{Granularity, ParseOptions};
+
+const FIRST: &str = "The highstorm had passed by the time Kaladin climbed out of the chasm, and the plateau was slick with crem.";
+const LAST: &str = "Far away, someone else was listening.";
+
+#[test]
+fn first_and_last_paragraphs_are_records() {
+    for context_radius in [0, 1, 3] {
+        let options = ParseOptions {
+            context_radius,
+            ..ParseOptions::default()
+        };
+        let records = parse(&book(false), &options, "scene_breaks.xhtml");
+        let texts: Vec<&str> = records
+            .iter()
+            .map(|it| it.searchable_text.as_str())
+            .collect();
+        assert_eq!(texts.first(), Some(&FIRST), "radius {context_radius}");
+        assert_eq!(texts.last(), Some(&LAST), "radius {context_radius}");
+    }
+}
+
+#[test]
+fn edges_have_one_sided_context() {
+    let options = ParseOptions {
+        context_radius: 2,
+        ..ParseOptions::default()
+    };
+    let records = parse(&book(false), &options, "scene_breaks.xhtml");
+    let first = &records[0].display_text;
+    assert!(first.starts_with("The highstorm"), "{first}");
+    assert_eq!(first.matches("</p><p>").count(), 2, "{first}");
+    // The last paragraph follows a scene break, so it has no context at all
+    let last = &records[records.len() - 1].display_text;
+    assert_eq!(last, LAST);
+}
+
+#[test]
+fn sentence_records_cover_the_edges() {
+    let options = ParseOptions {
+        granularity: Granularity::Sentence,
+        ..ParseOptions::default()
+    };
+    let records = parse(&book(false), &options, "scene_breaks.xhtml");
+    assert_eq!(records[0].searchable_text, FIRST);
+    assert_eq!(records[records.len() - 1].searchable_text, LAST);
+}
@@ -0,0 +1,93 @@
+//! Helpers shared by the integration tests.
+
+// Each test binary only uses some of them
+#![allow(dead_code)]
+
+use std::{fs, path::Path};
+
+use cosmere_epub_parser::{Chapter, ChapterParser, IndexableBook, OutputSchema, ParseOptions};
+
+pub fn book(has_epigraphs: bool) -> IndexableBook {
+    IndexableBook {
+        title: String::from("Fixtures"),
+        has_epigraphs,
+        ..IndexableBook::default()
+    }
+}
+
+/// Every record parsed from `tests/fixtures/<file_name>`, as chapter 1.
+pub fn parse(book: &IndexableBook, options: &ParseOptions, file_name: &str) -> Vec<OutputSchema> {
+    let html = fs::read(Path::new("tests/fixtures").join(file_name)).expect("fixture exists");
+    let chapter = Chapter {
+        html: &html,
+        title: "Chapter 1",
+        spine_id: "chapter01",
+        spine_index: 0,
+        file_name,
+    };
+    ChapterParser::new(book, Path::new("fixtures.epub"), options)
+        .expect("options are valid")
+        .parse_chapter(&chapter)
+        .expect("fixture parses")
+        .records
+}
+
+/// A minimal ePub titled `title`, with a spine of `chapters` (the contents of
+/// each `<body>`) as `c00.xhtml`, `c01.xhtml`, and so on, and `nav_map` as the
+/// inside of its NCX `<navMap>`.
+pub fn epub(title: &str, chapters: &[&str], nav_map: &str) -> Vec<u8> {
+    use std::io::{Cursor, Write};
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    let mut manifest =
+        String::from(r#"<item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>"#);
+    let mut spine = String::new();
+    for index in 0..chapters.len() {
+        manifest.push_str(&format!(
+            r#"<item id="c{index:02}" href="c{index:02}.xhtml" media-type="application/xhtml+xml"/>"#
+        ));
+        spine.push_str(&format!(r#"<itemref idref="c{index:02}"/>"#));
+    }
+    let mut files = vec![
+        (
+            String::from("META-INF/container.xml"),
+            String::from(
+                r#"<?xml version="1.0"?><container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+            ),
+        ),
+        (
+            String::from("OEBPS/content.opf"),
+            format!(
+                r#"<?xml version="1.0"?><package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="id"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>{title}</dc:title><dc:identifier id="id">{title}</dc:identifier><dc:language>en</dc:language></metadata><manifest>{manifest}</manifest><spine toc="ncx">{spine}</spine></package>"#
+            ),
+        ),
+        (
+            String::from("OEBPS/toc.ncx"),
+            format!(
+                r#"<?xml version="1.0"?><ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1"><head/><docTitle><text>{title}</text></docTitle><navMap>{nav_map}</navMap></ncx>"#
+            ),
+        ),
+    ];
+    for (index, body) in chapters.iter().enumerate() {
+        files.push((
+            format!("OEBPS/c{index:02}.xhtml"),
+            format!(
+                r#"<?xml version="1.0" encoding="utf-8"?><html xmlns="http://www.w3.org/1999/xhtml"><head><title>c{index:02}</title></head><body>{body}</body></html>"#
+            ),
+        ));
+    }
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    zip.start_file("mimetype", stored)
+        .expect("zip entry starts");
+    zip.write_all(b"application/epub+zip")
+        .expect("zip entry is written");
+    for (name, contents) in files {
+        zip.start_file(name, FileOptions::default())
+            .expect("zip entry starts");
+        zip.write_all(contents.as_bytes())
+            .expect("zip entry is written");
+    }
+    zip.finish().expect("zip is finished").into_inner()
+}
@@ -0,0 +1,120 @@
+//! Chapters can be rendered with a decorator other than `MyDecorator`.
+
+mod common;
+
+use common::{book, parse};
+use cosmere_epub_parser::{DecoratorFactory, MyDecorator, ParseOptions};
+use html2text::render::text_renderer::{TaggedLine, TextDecorator};
+
+/// `MyDecorator`, but keeping bold text as `<strong>`.
+#[derive(Clone, Debug)]
+struct KeepStrong(MyDecorator);
+
+impl TextDecorator for KeepStrong {
+    type Annotation = ();
+
+    fn decorate_code_start(&mut self) -> (String, Self::Annotation) {
+        self.0.decorate_code_start()
+    }
+
+    fn decorate_code_end(&mut self) -> String {
+        self.0.decorate_code_end()
+    }
+
+    fn decorate_em_start(&mut self) -> (String, Self::Annotation) {
+        self.0.decorate_em_start()
+    }
+
+    fn decorate_em_end(&mut self) -> String {
+        self.0.decorate_em_end()
+    }
+
+    fn decorate_image(&mut self, src: &str, title: &str) -> (String, Self::Annotation) {
+        self.0.decorate_image(src, title)
+    }
+
+    fn decorate_link_start(&mut self, url: &str) -> (String, Self::Annotation) {
+        self.0.decorate_link_start(url)
+    }
+
+    fn decorate_link_end(&mut self) -> String {
+        self.0.decorate_link_end()
+    }
+
+    fn decorate_preformat_first(&mut self) -> Self::Annotation {}
+
+    fn decorate_preformat_cont(&mut self) -> Self::Annotation {}
+
+    fn decorate_strikeout_start(&mut self) -> (String, Self::Annotation) {
+        self.0.decorate_strikeout_start()
+    }
+
+    fn decorate_strikeout_end(&mut self) -> String {
+        self.0.decorate_strikeout_end()
+    }
+
+    fn decorate_strong_start(&mut self) -> (String, Self::Annotation) {
+        (String::from("<strong>"), ())
+    }
+
+    fn decorate_strong_end(&mut self) -> String {
+        String::from("</strong>")
+    }
+
+    fn header_prefix(&mut self, level: usize) -> String {
+        self.0.header_prefix(level)
+    }
+
+    fn quote_prefix(&mut self) -> String {
+        self.0.quote_prefix()
+    }
+
+    fn ordered_item_prefix(&mut self, i: i64) -> String {
+        self.0.ordered_item_prefix(i)
+    }
+
+    fn unordered_item_prefix(&mut self) -> String {
+        self.0.unordered_item_prefix()
+    }
+
+    fn finalise(&mut self, links: Vec<String>) -> Vec<TaggedLine<Self::Annotation>> {
+        self.0.finalise(links)
+    }
+
+    fn make_subblock_decorator(&self) -> Self {
+        Self(self.0.make_subblock_decorator())
+    }
+}
+
+#[test]
+fn bold_text_is_dropped_by_default() {
+    let records = parse(&book(false), &ParseOptions::default(), "scene_breaks.xhtml");
+    let last = records.last().expect("the fixture has records");
+    assert_eq!(last.display_text, "Far away, someone else was listening.");
+}
+
+#[test]
+fn a_custom_decorator_keeps_its_markup() {
+    let options = ParseOptions {
+        decorator: DecoratorFactory::new(|options| {
+            KeepStrong(MyDecorator::with_links(options.keep_links))
+        }),
+        ..ParseOptions::default()
+    };
+    let records = parse(&book(false), &options, "scene_breaks.xhtml");
+    let last = records.last().expect("the fixture has records");
+    assert_eq!(
+        last.display_text,
+        "Far away, someone <strong>else</strong> was listening."
+    );
+    // Everything else renders the same as with `MyDecorator`
+    let default = parse(&book(false), &ParseOptions::default(), "scene_breaks.xhtml");
+    assert_eq!(records.len(), default.len());
+    assert_eq!(records[0].display_text, default[0].display_text);
+}
+
+#[test]
+fn kept_link_hrefs_are_escaped() {
+    let (start, ()) = MyDecorator::with_links(true).decorate_link_start("notes.xhtml?a=1&b=<2>\"");
+    assert_eq!(start, "<a href=\"notes.xhtml?a=1&amp;b=&lt;2&gt;&quot;\">");
+}
@@ -0,0 +1,48 @@
+//! Drop caps set apart from the rest of their word are joined back onto it.
+
+mod common;
+
+use common::{book, parse};
+use cosmere_epub_parser::ParseOptions;
+
+fn texts() -> Vec<String> {
+    parse(&book(false), &ParseOptions::default(), "drop_caps.xhtml")
+        .into_iter()
+        .map(|it| it.searchable_text)
+        .collect()
+}
+
+#[test]
+fn block_drop_caps_join_the_next_paragraph() {
+    let texts = texts();
+    assert_eq!(
+        texts[0],
+        "When the highstorm broke, Kaladin was already running."
+    );
+    assert!(!texts.iter().any(|it| it == "W"), "{texts:?}");
+}
+
+#[test]
+fn drop_cap_spans_join_their_word() {
+    let texts = texts();
+    assert_eq!(texts[1], "Shallan counted the guards twice.");
+    // No drop-cap class, but a lone capital opening the paragraph
+    assert_eq!(texts[2], "Teft grunted and looked away.");
+    assert_eq!(texts[3], "Hoid smiled at nobody in particular.");
+}
+
+#[test]
+fn one_letter_words_keep_their_space() {
+    let texts = texts();
+    assert_eq!(texts[4], "A storm was coming, and everyone knew it.");
+    assert_eq!(texts[5], "It was quiet on the plateau.");
+}
+
+#[test]
+fn other_single_letters_are_left_alone() {
+    let texts = texts();
+    assert_eq!(
+        texts[6],
+        "Nothing else on the page was a drop cap, not E or F."
+    );
+}
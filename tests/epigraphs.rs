@@ -0,0 +1,51 @@
+//! A chapter's epigraph is picked out of its opening lines without taking any
+//! of the prose with it.
+
+mod common;
+
+use common::{book, parse};
+use cosmere_epub_parser::ParseOptions;
+
+#[test]
+fn an_italic_line_after_prose_is_not_an_epigraph() {
+    let records = parse(
+        &book(true),
+        &ParseOptions::default(),
+        "italic_thought.xhtml",
+    );
+    let texts: Vec<&str> = records
+        .iter()
+        .map(|it| it.searchable_text.as_str())
+        .collect();
+    assert_eq!(
+        texts,
+        [
+            "Kaladin pressed his back to the chasm wall and listened to the rain.",
+            "I will not let them die.",
+            "He pushed off the stone and went after the others.",
+        ]
+    );
+    assert!(records.iter().all(|it| it.epigraph.is_none()));
+}
+
+#[test]
+fn italic_lines_opening_a_chapter_are_its_epigraph() {
+    let records = parse(
+        &book(true),
+        &ParseOptions::default(),
+        "italic_epigraph.xhtml",
+    );
+    assert_eq!(records.len(), 2);
+    assert_eq!(
+        records[0].epigraph.as_deref(),
+        Some("“The love of men is a frigid thing.”")
+    );
+    assert_eq!(
+        records[0].epigraph_source.as_deref(),
+        Some("Collected on Nanes, 1172")
+    );
+    assert_eq!(
+        records[1].searchable_text,
+        "Kaladin pressed his back to the chasm wall and listened to the rain."
+    );
+}
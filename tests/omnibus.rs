@@ -0,0 +1,91 @@
+//! The works of an omnibus without a configured range are found by their
+//! table of contents entries.
+
+mod common;
+
+use std::io::Cursor;
+
+use common::epub;
+use cosmere_epub_parser::{toc_range, validated_chapter_range, IndexableBook, Work};
+use epub::doc::EpubDoc;
+
+fn nav_point(play_order: usize, label: &str, file: &str, children: &str) -> String {
+    format!(
+        r#"<navPoint id="n{play_order}" playOrder="{play_order}"><navLabel><text>{label}</text></navLabel><content src="{file}"/>{children}</navPoint>"#
+    )
+}
+
+fn omnibus() -> EpubDoc<Cursor<Vec<u8>>> {
+    let chapters = [
+        "<h1>Contents</h1>",
+        "<h1>The Selish System</h1>",
+        "<h1>The Hope of Elantris</h1><p>Matisse hurried.</p>",
+        "<p>More of it.</p>",
+        "<h1>Postscript</h1><p>A note.</p>",
+        "<h1>Allomancer Jak</h1>",
+        "<h2>Episode Twenty-Eight</h2><p>Handerwym!</p>",
+        "<h1>Ars Arcanum</h1><p>Notes.</p>",
+    ];
+    let nav_map = [
+        nav_point(1, "Contents", "c00.xhtml", ""),
+        nav_point(
+            2,
+            "The Selish System",
+            "c01.xhtml",
+            &[
+                nav_point(3, "The Hope of Elantris", "c02.xhtml", ""),
+                nav_point(4, "Postscript", "c04.xhtml", ""),
+            ]
+            .concat(),
+        ),
+        nav_point(
+            5,
+            "Allomancer Jak and the Pits of Eltania, Episodes Twenty-Eight through Thirty",
+            "c05.xhtml",
+            &nav_point(6, "Episode Twenty-Eight", "c06.xhtml#start", ""),
+        ),
+        nav_point(7, "Ars Arcanum", "c07.xhtml", ""),
+    ]
+    .concat();
+    let bytes = epub("Arcanum Unbounded", &chapters, &nav_map);
+    EpubDoc::from_reader(Cursor::new(bytes)).expect("the ePub opens")
+}
+
+fn work(title: &str) -> IndexableBook {
+    let book = IndexableBook {
+        title: String::from("Arcanum Unbounded"),
+        works: vec![Work {
+            title: String::from(title),
+            toc_label: None,
+            first_chapter_index: None,
+            last_chapter_index: None,
+            skippable_chapters: Vec::new(),
+        }],
+        ..IndexableBook::default()
+    };
+    book.works()[0].clone().into_owned()
+}
+
+#[test]
+fn a_work_runs_up_to_the_next_entry() {
+    let mut doc = omnibus();
+    assert_eq!(toc_range(&mut doc, "The Hope of Elantris"), Some((2, 3)));
+    assert_eq!(
+        validated_chapter_range(&work("The Hope of Elantris"), &mut doc).unwrap(),
+        (2, 3)
+    );
+}
+
+#[test]
+fn entries_nested_under_a_work_are_part_of_it() {
+    let mut doc = omnibus();
+    assert_eq!(toc_range(&mut doc, "allomancer jak"), Some((5, 6)));
+}
+
+#[test]
+fn a_work_missing_from_the_contents_is_an_error() {
+    let mut doc = omnibus();
+    assert_eq!(toc_range(&mut doc, "Edgedancer"), None);
+    let error = validated_chapter_range(&work("Edgedancer"), &mut doc).unwrap_err();
+    assert!(error.to_string().contains("Edgedancer"), "{error}");
+}
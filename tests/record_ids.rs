@@ -0,0 +1,56 @@
+//! Every record of a chapter gets an id of its own, even when its text
+//! repeats, so upserting on `id` doesn't drop any.
+
+mod common;
+
+use std::collections::HashSet;
+
+use common::{book, parse};
+use cosmere_epub_parser::{Granularity, ParseOptions};
+
+#[test]
+fn equal_sentences_in_one_paragraph_get_different_ids() {
+    let options = ParseOptions {
+        granularity: Granularity::Sentence,
+        ..ParseOptions::default()
+    };
+    let records = parse(&book(false), &options, "repeated_sentences.xhtml");
+    let runs: Vec<_> = records
+        .iter()
+        .filter(|it| it.searchable_text == "Run.")
+        .collect();
+    assert_eq!(runs.len(), 2, "{records:?}");
+    assert_eq!(runs[0].paragraph_index, runs[1].paragraph_index);
+    assert_ne!(runs[0].id, runs[1].id);
+}
+
+#[test]
+fn ids_are_unique_within_a_chapter() {
+    for granularity in [Granularity::Paragraph, Granularity::Sentence] {
+        let options = ParseOptions {
+            granularity,
+            ..ParseOptions::default()
+        };
+        let records = parse(&book(false), &options, "repeated_sentences.xhtml");
+        let ids: HashSet<&str> = records.iter().map(|it| it.id.as_str()).collect();
+        assert_eq!(ids.len(), records.len(), "{granularity:?}");
+    }
+}
+
+#[test]
+fn ids_stay_the_same_from_run_to_run() {
+    let first = parse(
+        &book(false),
+        &ParseOptions::default(),
+        "repeated_sentences.xhtml",
+    );
+    let second = parse(
+        &book(false),
+        &ParseOptions::default(),
+        "repeated_sentences.xhtml",
+    );
+    let ids = |records: &[cosmere_epub_parser::OutputSchema]| {
+        records.iter().map(|it| it.id.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(ids(&first), ids(&second));
+}
@@ -0,0 +1,61 @@
+//! Paragraphs rendered at a finite width are joined back up, so records come
+//! out as they do unwrapped.
+
+mod common;
+
+use common::{book, parse};
+use cosmere_epub_parser::{OutputSchema, ParseOptions};
+
+fn texts(file_name: &str, render_width: usize) -> Vec<(String, Option<String>)> {
+    let options = ParseOptions {
+        render_width,
+        ..ParseOptions::default()
+    };
+    parse(&book(true), &options, file_name)
+        .into_iter()
+        .map(|it: OutputSchema| (it.searchable_text, it.style_tag))
+        .collect()
+}
+
+#[test]
+fn wrapping_leaves_records_alone() {
+    for file_name in ["line_breaks.xhtml", "epigraph.xhtml", "scene_breaks.xhtml"] {
+        let unwrapped = texts(file_name, usize::MAX);
+        for width in [30, 50, 80] {
+            assert_eq!(texts(file_name, width), unwrapped, "{file_name} at {width}");
+        }
+    }
+}
+
+#[test]
+fn line_breaks_are_kept() {
+    let texts = texts("line_breaks.xhtml", 30);
+    let start = texts
+        .iter()
+        .position(|(_, class)| class.as_deref() == Some("Song"))
+        .expect("the song is parsed");
+    let song: Vec<&str> = texts[start..start + 4]
+        .iter()
+        .map(|(text, _)| text.as_str())
+        .collect();
+    assert_eq!(
+        song,
+        [
+            "Sing of the storm,",
+            "and the light that it brings,",
+            "sing of the ten",
+            "who were kings."
+        ]
+    );
+}
+
+#[test]
+fn long_words_are_joined_without_a_space() {
+    let texts = texts("line_breaks.xhtml", 20);
+    assert!(
+        texts
+            .iter()
+            .any(|(text, _)| text.contains("Vorinalsongofmourningandremembrance")),
+        "{texts:?}"
+    );
+}
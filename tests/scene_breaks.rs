@@ -0,0 +1,34 @@
+//! A centered blank paragraph is a scene break, but a centered image isn't,
+//! even one with no `alt` text to leave behind.
+
+mod common;
+
+use common::{book, parse};
+use cosmere_epub_parser::ParseOptions;
+
+fn display_texts() -> Vec<String> {
+    let options = ParseOptions {
+        context_radius: 1,
+        ..ParseOptions::default()
+    };
+    parse(&book(false), &options, "centered_images.xhtml")
+        .into_iter()
+        .map(|it| it.display_text)
+        .collect()
+}
+
+#[test]
+fn a_centered_image_is_not_a_scene_break() {
+    let texts = display_texts();
+    assert!(texts[0].contains("Kelsier tapped"), "{}", texts[0]);
+}
+
+#[test]
+fn a_centered_blank_line_is_a_scene_break() {
+    let texts = display_texts();
+    assert!(!texts[1].contains("Sazed"), "{}", texts[1]);
+    assert_eq!(
+        texts[2],
+        "Elsewhere, Sazed closed his copperminds for the night."
+    );
+}
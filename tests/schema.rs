@@ -0,0 +1,31 @@
+//! Records are checked against the output schema, `$ref`s and all.
+
+mod common;
+
+use common::{book, parse};
+use cosmere_epub_parser::{output_schema, schema_violations, ParseOptions};
+use serde_json::Value;
+
+fn record() -> Value {
+    let records = parse(&book(false), &ParseOptions::default(), "scene_breaks.xhtml");
+    serde_json::to_value(&records[0]).expect("records serialize")
+}
+
+#[test]
+fn parsed_records_match_the_schema() {
+    assert_eq!(
+        schema_violations(&output_schema(), &record()),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn an_unknown_section_type_is_a_violation() {
+    let mut record = record();
+    record["section_type"] = Value::from("Appendix");
+    let violations = schema_violations(&output_schema(), &record);
+    assert!(
+        violations.iter().any(|it| it.starts_with("section_type")),
+        "{violations:?}"
+    );
+}
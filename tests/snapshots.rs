@@ -0,0 +1,47 @@
+//! Snapshots of the records parsed from small chapter fixtures, so a change to
+//! the cleanups or the decorator shows up as a diff in review.
+//!
+//! After an intended change, run `cargo insta review` (or set
+//! `INSTA_UPDATE=always`) to accept the new output.
+
+mod common;
+
+use common::{book, parse};
+use cosmere_epub_parser::{Granularity, ParseOptions};
+
+#[test]
+fn scene_breaks_and_emphasis() {
+    let records = parse(&book(false), &ParseOptions::default(), "scene_breaks.xhtml");
+    insta::assert_json_snapshot!(records);
+}
+
+#[test]
+fn scene_breaks_by_sentence() {
+    let options = ParseOptions {
+        granularity: Granularity::Sentence,
+        ..ParseOptions::default()
+    };
+    let records = parse(&book(false), &options, "scene_breaks.xhtml");
+    insta::assert_json_snapshot!(records);
+}
+
+#[test]
+fn scene_breaks_clip_wider_context() {
+    let options = ParseOptions {
+        context_radius: 2,
+        scene_start_marker: Some(String::from("* * *")),
+        ..ParseOptions::default()
+    };
+    let records = parse(&book(false), &options, "scene_breaks.xhtml");
+    insta::assert_json_snapshot!(records);
+}
+
+#[test]
+fn epigraph_footnote_and_small_caps() {
+    let options = ParseOptions {
+        small_caps: true,
+        ..ParseOptions::default()
+    };
+    let records = parse(&book(true), &options, "epigraph.xhtml");
+    insta::assert_json_snapshot!(records);
+}
@@ -0,0 +1,117 @@
+//! ePubs read from inside zip archives and from URLs.
+
+mod common;
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    net::TcpListener,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::Arc,
+    thread,
+};
+
+use common::epub;
+use cosmere_epub_parser::{EpubInput, EpubSource};
+use tempfile::TempDir;
+use zip::{write::FileOptions, ZipWriter};
+
+fn tress() -> Vec<u8> {
+    epub(
+        "Tress of the Emerald Sea",
+        &["<p>Tress lived on a rock.</p>"],
+        "",
+    )
+}
+
+/// A zip at `series.zip` in a new directory holding `books/tress.epub` and a
+/// file that isn't an ePub.
+fn series_zip() -> TempDir {
+    let dir = TempDir::new().expect("temp dir is made");
+    let mut zip = ZipWriter::new(File::create(dir.path().join("series.zip")).unwrap());
+    zip.start_file("books/tress.epub", FileOptions::default())
+        .unwrap();
+    zip.write_all(&tress()).unwrap();
+    zip.start_file("books/notes.txt", FileOptions::default())
+        .unwrap();
+    zip.write_all(b"Not a book").unwrap();
+    zip.finish().unwrap();
+    dir
+}
+
+fn title(source: &EpubSource) -> String {
+    let doc = source.load().unwrap().open().expect("the ePub opens");
+    doc.mdata("title").expect("the ePub has a title")
+}
+
+#[test]
+fn an_archive_stands_for_the_epubs_in_it() {
+    let dir = series_zip();
+    let archive = dir.path().join("series.zip");
+    let members = EpubSource::archive_members(&archive).unwrap();
+    assert_eq!(
+        members,
+        [EpubSource::ArchiveMember {
+            archive: archive.clone(),
+            member: String::from("books/tress.epub")
+        }]
+    );
+    assert_eq!(title(&members[0]), "Tress of the Emerald Sea");
+}
+
+#[test]
+fn a_path_inside_an_archive_picks_out_one_member() {
+    let dir = series_zip();
+    let path = dir.path().join("series.zip/books/tress.epub");
+    let source = EpubSource::archive_member(&path).expect("the path is inside the zip");
+    assert_eq!(
+        source,
+        EpubSource::ArchiveMember {
+            archive: dir.path().join("series.zip"),
+            member: String::from("books/tress.epub")
+        }
+    );
+    assert_eq!(source.name(), path);
+    assert_eq!(title(&source), "Tress of the Emerald Sea");
+    assert_eq!(EpubSource::archive_member(Path::new("tress.epub")), None);
+}
+
+#[test]
+fn a_missing_member_is_an_error() {
+    let dir = series_zip();
+    let source = EpubSource::archive_member(&dir.path().join("series.zip/books/mistborn.epub"))
+        .expect("the path is inside the zip");
+    assert!(source.load().is_err());
+}
+
+#[test]
+fn a_url_is_downloaded_once() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/tress.epub", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let served = Arc::clone(&requests);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            served.fetch_add(1, Ordering::SeqCst);
+            let body = tress();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+        }
+    });
+
+    let input = EpubInput::new(EpubSource::url(&url).expect("it's a URL"));
+    for _ in 0..3 {
+        let doc = input.load().unwrap().open().expect("the ePub opens");
+        assert_eq!(doc.mdata("title").unwrap(), "Tress of the Emerald Sea");
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+}